@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use meshtastic::protobufs::config::{LoRaConfig, lo_ra_config::ModemPreset};
+
+/// The LoRa modulation of the radio, to estimate how long packets occupy the channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoraModulation {
+    pub bandwidth_khz: f32,
+    pub spread_factor: u32,
+    /// The denominator of the coding rate, 5 - 8 for 4/5 - 4/8.
+    pub coding_rate: u32,
+}
+
+impl LoraModulation {
+    /// The firmware default preset.
+    pub const LONG_FAST: Self = Self::new(250.0, 11, 5);
+
+    /// The symbols of the preamble of Meshtastic packets.
+    const PREAMBLE_SYMBOLS: f64 = 16.0;
+    /// The packet header and the protobuf overhead around the payload.
+    const OVERHEAD_BYTES: usize = 16 + 8;
+
+    const fn new(bandwidth_khz: f32, spread_factor: u32, coding_rate: u32) -> Self {
+        Self {
+            bandwidth_khz,
+            spread_factor,
+            coding_rate,
+        }
+    }
+
+    pub fn from_preset(preset: ModemPreset) -> Self {
+        // Older radios still report the deprecated slow presets.
+        #[allow(deprecated)]
+        match preset {
+            ModemPreset::ShortTurbo => Self::new(500.0, 7, 5),
+            ModemPreset::ShortFast => Self::new(250.0, 7, 5),
+            ModemPreset::ShortSlow => Self::new(250.0, 8, 5),
+            ModemPreset::MediumFast => Self::new(250.0, 9, 5),
+            ModemPreset::MediumSlow => Self::new(250.0, 10, 5),
+            ModemPreset::LongFast => Self::LONG_FAST,
+            ModemPreset::LongModerate => Self::new(125.0, 11, 8),
+            ModemPreset::LongSlow => Self::new(125.0, 12, 8),
+            ModemPreset::VeryLongSlow => Self::new(62.5, 12, 8),
+        }
+    }
+
+    /// The airtime of a packet with `payload_len` bytes of payload.
+    ///
+    /// See the LoRa time on air formula in the Semtech SX1276 datasheet.
+    pub fn airtime(&self, payload_len: usize) -> Duration {
+        let spread_factor = self.spread_factor as f64;
+        let symbol_s = 2f64.powf(spread_factor) / (self.bandwidth_khz as f64 * 1000.0);
+        // Low data rate optimization is on for symbols longer than 16 ms.
+        let low_data_rate = if symbol_s > 0.016 { 1.0 } else { 0.0 };
+
+        let bytes = (payload_len + Self::OVERHEAD_BYTES) as f64;
+        let payload_symbols = 8.0
+            + ((8.0 * bytes - 4.0 * spread_factor + 28.0 + 16.0)
+                / (4.0 * (spread_factor - 2.0 * low_data_rate)))
+                .ceil()
+                .max(0.0)
+                * self.coding_rate as f64;
+
+        Duration::from_secs_f64((Self::PREAMBLE_SYMBOLS + 4.25 + payload_symbols) * symbol_s)
+    }
+}
+
+impl Default for LoraModulation {
+    fn default() -> Self {
+        Self::LONG_FAST
+    }
+}
+
+impl From<&LoRaConfig> for LoraModulation {
+    fn from(lora: &LoRaConfig) -> Self {
+        let preset = Self::from_preset(
+            ModemPreset::try_from(lora.modem_preset).unwrap_or(ModemPreset::LongFast),
+        );
+        if lora.use_preset {
+            return preset;
+        };
+
+        // The firmware rounds the fractional bandwidths down in the config.
+        let bandwidth_khz = match lora.bandwidth {
+            0 => preset.bandwidth_khz,
+            31 => 31.25,
+            62 => 62.5,
+            203 => 203.125,
+            406 => 406.25,
+            812 => 812.5,
+            bandwidth => bandwidth as f32,
+        };
+
+        Self {
+            bandwidth_khz,
+            spread_factor: match lora.spread_factor {
+                0 => preset.spread_factor,
+                spread_factor => spread_factor,
+            },
+            coding_rate: match lora.coding_rate {
+                0 => preset.coding_rate,
+                coding_rate => coding_rate,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_fast_airtime() {
+        // SF11, 250 kHz, 4/5: 8.192 ms symbols, 16 + 4.25 preamble and 8 + 41 * 5 payload symbols.
+        let airtime = LoraModulation::from_preset(ModemPreset::LongFast).airtime(200);
+
+        assert_eq!(airtime.as_micros(), 1_910_784);
+    }
+
+    #[test]
+    fn long_slow_uses_low_data_rate_optimization() {
+        // SF12, 125 kHz, 4/8: 32.768 ms symbols, 16 + 4.25 preamble and 8 + 15 * 8 payload symbols.
+        #[allow(deprecated)]
+        let airtime = LoraModulation::from_preset(ModemPreset::LongSlow).airtime(50);
+
+        assert_eq!(airtime.as_micros(), 4_857_856);
+    }
+}
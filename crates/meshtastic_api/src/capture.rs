@@ -0,0 +1,207 @@
+//! Record the radio stream to a file and replay it.
+//!
+//! A capture has one `FromRadio` per line: the UNIX timestamp in ms it was received at and the hex encoded protobuf.
+//! Empty lines and lines starting with `#` are ignored.
+
+use std::path::Path;
+
+use meshtastic::{Message, protobufs::FromRadio};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+use crate::error::Error;
+
+const HEADER: &str = "# Meshtastic capture: <UNIX timestamp ms> <hex encoded FromRadio>\n";
+const BUFFER_SIZE: usize = 4096;
+
+/// Appends every received `FromRadio` to a capture file.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    sender: tokio::sync::mpsc::UnboundedSender<(u64, FromRadio)>,
+}
+
+impl Capture {
+    /// Append to the capture at `path`, create it if it does not exist.
+    pub async fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        if file.metadata().await?.len() == 0 {
+            file.write_all(HEADER.as_bytes()).await?;
+        };
+        tracing::info!("Capturing the radio stream to {}", path.as_ref().display());
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel::<(u64, FromRadio)>();
+        tokio::task::spawn(async move {
+            while let Some((timestamp_ms, from_radio)) = receiver.recv().await {
+                let line = format!(
+                    "{} {}\n",
+                    timestamp_ms,
+                    hex::encode(from_radio.encode_to_vec())
+                );
+
+                if let Err(e) = file.write_all(line.as_bytes()).await {
+                    tracing::error!("Failed to write the capture: {}", e);
+                    return;
+                };
+            }
+        });
+
+        Ok(Self { sender })
+    }
+
+    pub(crate) fn record(&self, from_radio: &FromRadio) {
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|now| now.as_millis() as u64)
+            .unwrap_or_default();
+
+        let _ = self.sender.send((timestamp_ms, from_radio.clone()));
+    }
+}
+
+/// Read the capture at `path` and feed it to the returned stream as the radio would.
+///
+/// `speed` accelerates the replay, 1 keeps the original timing, 0 replays as fast as possible.
+/// The stream stays open after the last packet, so the bot does not reconnect and replay it again.
+pub(crate) async fn replay(path: impl AsRef<Path>, speed: u32) -> Result<DuplexStream, Error> {
+    let capture = tokio::fs::read_to_string(&path).await?;
+    let frames = parse(&capture)?;
+    tracing::info!(
+        "Replaying {} packets from {}",
+        frames.len(),
+        path.as_ref().display()
+    );
+
+    let (bot, radio) = tokio::io::duplex(BUFFER_SIZE);
+    let (mut reader, mut writer) = tokio::io::split(radio);
+
+    // Drain what the bot sends, or its writes block once the buffer is full. Ends when the bot disconnects.
+    let drain_task = tokio::task::spawn(async move {
+        let mut drain = [0; BUFFER_SIZE];
+        while let Ok(len) = reader.read(&mut drain).await
+            && len > 0
+        {}
+    });
+
+    tokio::task::spawn(async move {
+        let mut previous_ms = frames.first().map(|(timestamp_ms, _)| *timestamp_ms);
+        for (timestamp_ms, from_radio) in frames {
+            if speed > 0
+                && let Some(previous_ms) = previous_ms
+            {
+                let delay_ms = timestamp_ms.saturating_sub(previous_ms) / speed as u64;
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+            };
+            previous_ms = Some(timestamp_ms);
+
+            if let Err(e) = writer.write_all(&crate::frame::encode(&from_radio)).await {
+                tracing::warn!("Replay stopped: {}", e);
+                return;
+            };
+        }
+
+        tracing::info!("Replay finished.");
+        // Keep the stream open until the bot disconnects, the bot would reconnect and replay it again.
+        let _ = drain_task.await;
+    });
+
+    Ok(bot)
+}
+
+fn parse(capture: &str) -> Result<Vec<(u64, FromRadio)>, Error> {
+    capture
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| {
+            let invalid = || Error::InvalidCapture(i + 1);
+
+            let (timestamp_ms, hex_frame) = line.trim().split_once(' ').ok_or_else(invalid)?;
+            let timestamp_ms = timestamp_ms.parse().map_err(|_| invalid())?;
+            let bytes = hex::decode(hex_frame).map_err(|_| invalid())?;
+            let from_radio = FromRadio::decode(bytes.as_slice()).map_err(|_| invalid())?;
+
+            Ok((timestamp_ms, from_radio))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use meshtastic::protobufs;
+
+    use super::*;
+
+    fn text_packet(id: u32, text: &str) -> FromRadio {
+        FromRadio {
+            id,
+            payload_variant: Some(protobufs::from_radio::PayloadVariant::Packet(
+                protobufs::MeshPacket {
+                    from: 0x42,
+                    id,
+                    payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+                        protobufs::Data {
+                            portnum: protobufs::PortNum::TextMessageApp as i32,
+                            payload: text.as_bytes().to_vec(),
+                            ..Default::default()
+                        },
+                    )),
+                    ..Default::default()
+                },
+            )),
+        }
+    }
+
+    async fn read_frame(stream: &mut DuplexStream) -> FromRadio {
+        let mut frame_start = [0; 2];
+        stream.read_exact(&mut frame_start).await.unwrap();
+        assert_eq!(frame_start, crate::frame::FRAME_START);
+
+        let len = stream.read_u16().await.unwrap();
+        let mut payload = vec![0; len as usize];
+        stream.read_exact(&mut payload).await.unwrap();
+
+        FromRadio::decode(payload.as_slice()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_capture() {
+        let path = std::env::temp_dir().join(format!("capture-test-{}", std::process::id()));
+        let _ = tokio::fs::remove_file(&path).await;
+        let recorded = vec![text_packet(1, "first"), text_packet(2, "second")];
+
+        let capture = Capture::create(&path).await.unwrap();
+        for from_radio in &recorded {
+            capture.record(from_radio);
+        }
+        drop(capture);
+
+        // The capture is written in the background.
+        let lines = recorded.len() + 1;
+        while tokio::fs::read_to_string(&path)
+            .await
+            .unwrap()
+            .lines()
+            .count()
+            < lines
+        {
+            tokio::task::yield_now().await;
+        }
+
+        let mut stream = replay(&path, 0).await.unwrap();
+        for from_radio in recorded {
+            assert_eq!(read_frame(&mut stream).await, from_radio);
+        }
+
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let capture = format!("{}1 08011a0308b424\n\nnot a frame\n", HEADER);
+
+        assert!(matches!(parse(&capture), Err(Error::InvalidCapture(4))));
+    }
+}
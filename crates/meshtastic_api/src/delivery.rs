@@ -0,0 +1,175 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use meshtastic::protobufs;
+
+use crate::packet::Target;
+
+/// The outcome of sending a packet with `want_ack`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The destination acknowledged the packet.
+    Acknowledged,
+    /// Another node rebroadcast the broadcast packet, the best there is for broadcasts.
+    ///
+    /// Direct messages keep waiting for the acknowledgement of their destination instead.
+    Relayed,
+    /// The packet could not be delivered.
+    Failed(RoutingError),
+    /// No routing packet arrived in time.
+    TimedOut,
+}
+
+/// The error reason of a routing packet. See `protobufs::routing::Error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutingError(pub i32);
+
+/// A sent packet waiting for its routing packet.
+#[derive(Debug)]
+pub struct PendingDelivery {
+    /// The id of the sent packet.
+    pub id: u32,
+    receiver: tokio::sync::oneshot::Receiver<Delivery>,
+    tracker: DeliveryTracker,
+}
+
+/// Matches routing packets to the sent packets they answer.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DeliveryTracker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Sender of the outcome and destination by packet id.
+    pending: HashMap<u32, (tokio::sync::oneshot::Sender<Delivery>, u32)>,
+    /// Routing packets that arrived before their packet was tracked by request id, source and reason.
+    unclaimed: VecDeque<(u32, u32, i32)>,
+}
+
+impl Delivery {
+    /// The outcome of a routing packet from `from` answering a packet to `destination`.
+    ///
+    /// `None` for the implicit acknowledgement of a direct message by a relaying node, it was not delivered yet.
+    fn from_routing(destination: u32, from: u32, reason: i32) -> Option<Self> {
+        if reason != protobufs::routing::Error::None as i32 {
+            Some(Self::Failed(RoutingError(reason)))
+        } else if from == destination {
+            Some(Self::Acknowledged)
+        } else if destination == Target::PRIMARY_CHANNEL_ID {
+            Some(Self::Relayed)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        match self {
+            Self::Acknowledged | Self::Relayed => true,
+            Self::Failed(_) | Self::TimedOut => false,
+        }
+    }
+}
+
+impl PendingDelivery {
+    /// Wait for the outcome, at most `timeout`.
+    pub async fn outcome(self, timeout: std::time::Duration) -> Delivery {
+        match tokio::time::timeout(timeout, self.receiver).await {
+            Ok(Ok(delivery)) => delivery,
+            _ => {
+                self.tracker.forget(self.id);
+                Delivery::TimedOut
+            }
+        }
+    }
+}
+
+impl DeliveryTracker {
+    /// How many routing packets of untracked packets are kept.
+    const MAX_UNCLAIMED: usize = 32;
+
+    /// Track the delivery of the sent packet `id`.
+    pub(crate) fn track(&self, id: u32, destination: u32) -> PendingDelivery {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+
+        let mut inner = self.lock();
+        let unclaimed = inner
+            .unclaimed
+            .iter()
+            .filter(|(request_id, _, _)| *request_id == id)
+            .find_map(|(_, from, reason)| Delivery::from_routing(destination, *from, *reason));
+        inner
+            .unclaimed
+            .retain(|(request_id, _, _)| *request_id != id);
+        match unclaimed {
+            Some(delivery) => {
+                let _ = sender.send(delivery);
+            }
+            None => {
+                let _ = inner.pending.insert(id, (sender, destination));
+            }
+        };
+
+        PendingDelivery {
+            id,
+            receiver,
+            tracker: self.clone(),
+        }
+    }
+
+    /// Resolve the packet `request_id` with a routing packet.
+    pub(crate) fn resolve(&self, request_id: u32, from: u32, routing: &protobufs::Routing) {
+        let reason = match routing.variant {
+            Some(protobufs::routing::Variant::ErrorReason(reason)) => reason,
+            _ => return,
+        };
+
+        let mut inner = self.lock();
+        match inner.pending.get(&request_id) {
+            Some((_, destination)) => {
+                let Some(delivery) = Delivery::from_routing(*destination, from, reason) else {
+                    return;
+                };
+                if let Some((sender, _)) = inner.pending.remove(&request_id) {
+                    let _ = sender.send(delivery);
+                };
+            }
+            None => {
+                if inner.unclaimed.len() >= Self::MAX_UNCLAIMED {
+                    let _ = inner.unclaimed.pop_front();
+                };
+                inner.unclaimed.push_back((request_id, from, reason));
+            }
+        };
+    }
+
+    fn forget(&self, id: u32) {
+        let _ = self.lock().pending.remove(&id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match protobufs::routing::Error::try_from(self.0) {
+            Ok(error) => write!(f, "{}", error.as_str_name()),
+            Err(_) => write!(f, "Unknown Routing Error {}", self.0),
+        }
+    }
+}
+
+impl std::fmt::Display for Delivery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Acknowledged => write!(f, "Acknowledged"),
+            Self::Relayed => write!(f, "Relayed"),
+            Self::Failed(error) => write!(f, "Failed: {}", error),
+            Self::TimedOut => write!(f, "Timed out"),
+        }
+    }
+}
@@ -4,14 +4,34 @@ pub enum Error {
     Meshtastic(#[from] meshtastic::errors::Error),
     #[error("Join Error: Failed to join a task: {0}")]
     JoinError(#[from] tokio::task::JoinError),
+    #[error("Serial Error: {0}")]
+    Serial(#[from] tokio_serial::Error),
+    #[error("USB device {0:04x}:{1:04x} not found")]
+    UsbDeviceNotFound(u16, u16),
+    #[error("The radio did not send its node info")]
+    MissingMyNodeInfo,
+    #[error("The radio did not answer within {0}s")]
+    ConnectTimeout(u64),
+    #[error("IO Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid capture: Line {0} is no `<timestamp> <hex>` pair of a FromRadio")]
+    InvalidCapture(usize),
+    #[cfg(feature = "fake")]
+    #[error("The fake radio was dropped")]
+    FakeRadioClosed,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum SendError {
     #[error(
-        "Message too Big: Message Size: {} > {} Max Message Size",
-        0,
-        crate::MAX_PAYLOAD_SIZE
+        "Message too Big: Message Size: {0} > {max} Max Message Size",
+        max = crate::MAX_PAYLOAD_SIZE
     )]
     TooBig(usize),
+    #[error("The sent packet was not echoed, its id is unknown")]
+    MissingPacketId,
+    #[error("Not connected to the radio")]
+    Disconnected,
+    #[error("Meshtastic Error: {0}")]
+    Meshtastic(#[from] meshtastic::errors::Error),
 }
@@ -0,0 +1,382 @@
+//! An in-memory radio to run the bot without hardware.
+//!
+//! `FakeRadio` speaks the serial stream protocol of the firmware: it answers the config request,
+//! relays injected `FromRadio` packets to the bot and hands every `ToRadio` packet the bot sends back to its owner.
+
+use meshtastic::{
+    Message,
+    protobufs::{self, FromRadio, ToRadio},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, DuplexStream},
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+};
+
+use crate::{
+    error::Error,
+    frame::{FRAME_START, MAX_FRAME_SIZE},
+    packet::Target,
+    transport::Transport,
+};
+
+const BUFFER_SIZE: usize = 4096;
+
+/// The test side of a fake radio.
+#[derive(Debug)]
+pub struct FakeRadio {
+    node_num: u32,
+    link: FakeLink,
+    control_sender: UnboundedSender<Control>,
+    sent_receiver: UnboundedReceiver<ToRadio>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// The bot side of a fake radio. Every connect opens a new in-memory stream to the radio.
+#[derive(Debug, Clone)]
+pub struct FakeLink {
+    connection_sender: UnboundedSender<DuplexStream>,
+}
+
+#[derive(Debug)]
+enum Control {
+    Inject(Box<FromRadio>),
+    DropConnection,
+}
+
+impl FakeRadio {
+    /// A radio with the node number `node_num`, the node number the bot gets.
+    pub fn new(node_num: u32) -> Self {
+        let (connection_sender, connection_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (control_sender, control_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let (sent_sender, sent_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let task = tokio::task::spawn(Self::serve(
+            node_num,
+            connection_receiver,
+            control_receiver,
+            sent_sender,
+        ));
+
+        Self {
+            node_num,
+            link: FakeLink { connection_sender },
+            control_sender,
+            sent_receiver,
+            task,
+        }
+    }
+
+    /// The transport to hand to `MeshtasticApi::new`.
+    pub fn transport(&self) -> Transport {
+        Transport::Fake(self.link.clone())
+    }
+
+    pub fn node_num(&self) -> u32 {
+        self.node_num
+    }
+
+    /// Send a packet to the bot.
+    pub fn inject(&self, from_radio: FromRadio) {
+        let _ = self
+            .control_sender
+            .send(Control::Inject(Box::new(from_radio)));
+    }
+
+    /// Send a text message from `from` to `to` on `channel`. Returns the packet id.
+    pub fn inject_text(&self, from: u32, to: Target, channel: u32, text: &str) -> u32 {
+        self.inject_data(
+            from,
+            to.into_id(),
+            channel,
+            protobufs::Data {
+                portnum: protobufs::PortNum::TextMessageApp as i32,
+                payload: text.as_bytes().to_vec(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Send a text message from `from` threaded as a reply to the packet `reply_id`. Returns the packet id.
+    pub fn inject_reply(&self, from: u32, to: Target, reply_id: u32, text: &str) -> u32 {
+        self.inject_data(
+            from,
+            to.into_id(),
+            0,
+            protobufs::Data {
+                portnum: protobufs::PortNum::TextMessageApp as i32,
+                payload: text.as_bytes().to_vec(),
+                reply_id,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Broadcast the user info of `from`. Returns the packet id.
+    pub fn inject_node_info(&self, from: u32, short_name: &str, long_name: &str) -> u32 {
+        let user = protobufs::User {
+            id: format!("!{:08x}", from),
+            short_name: short_name.to_string(),
+            long_name: long_name.to_string(),
+            ..Default::default()
+        };
+
+        self.inject_data(
+            from,
+            Target::PRIMARY_CHANNEL_ID,
+            0,
+            protobufs::Data {
+                portnum: protobufs::PortNum::NodeinfoApp as i32,
+                payload: user.encode_to_vec(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Answer the packet `request_id` from `from` with a routing packet.
+    ///
+    /// `protobufs::routing::Error::None` acknowledges the packet, everything else reports its failure.
+    pub fn inject_routing(
+        &self,
+        from: u32,
+        request_id: u32,
+        error_reason: protobufs::routing::Error,
+    ) -> u32 {
+        let routing = protobufs::Routing {
+            variant: Some(protobufs::routing::Variant::ErrorReason(
+                error_reason as i32,
+            )),
+        };
+
+        self.inject_data(
+            from,
+            self.node_num,
+            0,
+            protobufs::Data {
+                portnum: protobufs::PortNum::RoutingApp as i32,
+                payload: routing.encode_to_vec(),
+                request_id,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn inject_data(&self, from: u32, to: u32, channel: u32, data: protobufs::Data) -> u32 {
+        let id = meshtastic::utils::generate_rand_id();
+
+        self.inject(FromRadio {
+            id: 0,
+            payload_variant: Some(protobufs::from_radio::PayloadVariant::Packet(
+                protobufs::MeshPacket {
+                    from,
+                    to,
+                    channel,
+                    id,
+                    hop_start: crate::HOP_LIMIT,
+                    hop_limit: crate::HOP_LIMIT,
+                    payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)),
+                    ..Default::default()
+                },
+            )),
+        });
+
+        id
+    }
+
+    /// Close the stream to the bot as if the radio was unplugged. The bot may connect again.
+    pub fn drop_connection(&self) {
+        let _ = self.control_sender.send(Control::DropConnection);
+    }
+
+    /// The next packet the bot sent to the radio. `None` once the radio task stopped.
+    pub async fn next_sent(&mut self) -> Option<ToRadio> {
+        self.sent_receiver.recv().await
+    }
+
+    /// The next mesh packet the bot sent, skipping heartbeats and other radio control packets.
+    pub async fn next_sent_packet(&mut self) -> Option<protobufs::MeshPacket> {
+        while let Some(to_radio) = self.next_sent().await {
+            if let Some(protobufs::to_radio::PayloadVariant::Packet(mesh_packet)) =
+                to_radio.payload_variant
+            {
+                return Some(mesh_packet);
+            };
+        }
+
+        None
+    }
+
+    /// The next text message the bot sent with its target.
+    pub async fn next_sent_text(&mut self) -> Option<(Target, String)> {
+        while let Some(mesh_packet) = self.next_sent_packet().await {
+            if let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) =
+                &mesh_packet.payload_variant
+                && data.portnum == protobufs::PortNum::TextMessageApp as i32
+            {
+                return Some((
+                    Target::from(mesh_packet.to),
+                    String::from_utf8_lossy(&data.payload).to_string(),
+                ));
+            };
+        }
+
+        None
+    }
+
+    async fn serve(
+        node_num: u32,
+        mut connection_receiver: UnboundedReceiver<DuplexStream>,
+        mut control_receiver: UnboundedReceiver<Control>,
+        sent_sender: UnboundedSender<ToRadio>,
+    ) {
+        let (to_radio_sender, mut to_radio_receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut writer: Option<tokio::io::WriteHalf<DuplexStream>> = None;
+        // Packets injected while the bot was not connected, sent on the next connect.
+        let mut backlog: Vec<FromRadio> = Vec::new();
+
+        loop {
+            tokio::select! {
+                connection = connection_receiver.recv() => {
+                    let Some(connection) = connection else {
+                        break;
+                    };
+
+                    let (reader, new_writer) = tokio::io::split(connection);
+                    tokio::task::spawn(Self::read_frames(reader, to_radio_sender.clone()));
+                    writer = Some(new_writer);
+                    tracing::debug!("Fake radio connected.");
+                }
+                Some(to_radio) = to_radio_receiver.recv() => {
+                    if let Some(protobufs::to_radio::PayloadVariant::WantConfigId(config_id)) =
+                        to_radio.payload_variant
+                    {
+                        let mut answer = vec![
+                            FromRadio {
+                                id: 0,
+                                payload_variant: Some(protobufs::from_radio::PayloadVariant::MyInfo(
+                                    protobufs::MyNodeInfo {
+                                        my_node_num: node_num,
+                                        ..Default::default()
+                                    },
+                                )),
+                            },
+                            FromRadio {
+                                id: 0,
+                                payload_variant: Some(
+                                    protobufs::from_radio::PayloadVariant::ConfigCompleteId(config_id),
+                                ),
+                            },
+                        ];
+                        answer.append(&mut backlog);
+
+                        for from_radio in answer {
+                            Self::write_frame(&mut writer, &from_radio).await;
+                        }
+                    };
+
+                    if sent_sender.send(to_radio).is_err() {
+                        break;
+                    };
+                }
+                control = control_receiver.recv() => {
+                    match control {
+                        Some(Control::Inject(from_radio)) if writer.is_some() => {
+                            Self::write_frame(&mut writer, &from_radio).await;
+                        }
+                        Some(Control::Inject(from_radio)) => backlog.push(*from_radio),
+                        Some(Control::DropConnection) => {
+                            if let Some(mut stream) = writer.take() {
+                                let _ = stream.shutdown().await;
+                            };
+                            tracing::debug!("Fake radio dropped the connection.");
+                        }
+                        None => break,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Decode the frames of one connection until the bot closes it.
+    async fn read_frames(
+        mut reader: tokio::io::ReadHalf<DuplexStream>,
+        to_radio_sender: UnboundedSender<ToRadio>,
+    ) {
+        let mut payload = Vec::with_capacity(MAX_FRAME_SIZE);
+
+        loop {
+            // Skip everything up to the frame start, e.g. the wake up bytes.
+            let mut previous = 0;
+            loop {
+                let Ok(byte) = reader.read_u8().await else {
+                    return;
+                };
+                if [previous, byte] == FRAME_START {
+                    break;
+                };
+                previous = byte;
+            }
+
+            let Ok(len) = reader.read_u16().await else {
+                return;
+            };
+            if len as usize > MAX_FRAME_SIZE {
+                tracing::warn!("Fake radio: Frame too big: {} bytes", len);
+                continue;
+            };
+
+            payload.resize(len as usize, 0);
+            if reader.read_exact(&mut payload).await.is_err() {
+                return;
+            };
+
+            match ToRadio::decode(payload.as_slice()) {
+                Ok(to_radio) => {
+                    if to_radio_sender.send(to_radio).is_err() {
+                        return;
+                    };
+                }
+                Err(e) => tracing::warn!("Fake radio: Failed to decode ToRadio: {}", e),
+            };
+        }
+    }
+
+    async fn write_frame(
+        writer: &mut Option<tokio::io::WriteHalf<DuplexStream>>,
+        from_radio: &FromRadio,
+    ) {
+        let Some(stream) = writer else {
+            return;
+        };
+
+        if let Err(e) = stream.write_all(&crate::frame::encode(from_radio)).await {
+            tracing::warn!("Fake radio: Failed to write to the bot: {}", e);
+            *writer = None;
+        };
+    }
+}
+
+impl Drop for FakeRadio {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl FakeLink {
+    pub(crate) fn connect(&self) -> Result<DuplexStream, Error> {
+        let (bot, radio) = tokio::io::duplex(BUFFER_SIZE);
+        self.connection_sender
+            .send(radio)
+            .map_err(|_| Error::FakeRadioClosed)?;
+
+        Ok(bot)
+    }
+}
+
+impl PartialEq for FakeLink {
+    fn eq(&self, other: &Self) -> bool {
+        self.connection_sender
+            .same_channel(&other.connection_sender)
+    }
+}
+
+impl Eq for FakeLink {}
@@ -0,0 +1,19 @@
+//! The framing of the serial stream protocol the radio speaks.
+
+use meshtastic::Message;
+
+/// The first two bytes of every frame.
+pub(crate) const FRAME_START: [u8; 2] = [0x94, 0xc3];
+#[cfg(feature = "fake")]
+pub(crate) const MAX_FRAME_SIZE: usize = 512;
+
+/// Encode `message` into a frame.
+pub(crate) fn encode(message: &impl Message) -> Vec<u8> {
+    let payload = message.encode_to_vec();
+    let mut frame = Vec::with_capacity(FRAME_START.len() + 2 + payload.len());
+    frame.extend_from_slice(&FRAME_START);
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    frame
+}
@@ -1,44 +1,167 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
 use meshtastic::protobufs;
 use tokio::sync::mpsc::UnboundedReceiver;
 
 pub use meshtastic::protobufs::MyNodeInfo;
 
-use crate::{channel::Channel, node_id::NodeId, packet::Packet};
+use crate::{
+    airtime::LoraModulation,
+    capture::Capture,
+    channel::Channel,
+    delivery::{DeliveryTracker, PendingDelivery},
+    node_id::NodeId,
+    nodes::NodeDirectory,
+    packet::Packet,
+    router::BotRouter,
+    transport::Transport,
+};
 
+pub mod airtime;
+pub mod capture;
 pub mod channel;
+pub mod delivery;
 pub mod error;
+#[cfg(feature = "fake")]
+pub mod fake;
 pub mod node_id;
+pub mod nodes;
 pub mod packet;
+pub mod scheduler;
+pub mod transport;
+
+mod frame;
+mod router;
 
 pub const MAX_PAYLOAD_SIZE: usize = 200;
+/// The hop limit of packets sent by the bot itself. The firmware default.
+const HOP_LIMIT: u32 = 3;
+/// How long a connect may take until the radio sent its node info, e.g. a hanging serial port never does.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
 #[derive(Debug)]
 pub struct MeshtasticApi {
-    stream_api: meshtastic::api::ConnectedStreamApi,
+    transport: Transport,
+    shared: Shared,
+    /// `None` while disconnected.
+    connection: Option<Connection>,
     node_id: NodeId,
+    router: BotRouter,
+}
 
+/// The state shared with the listener of every connection.
+#[derive(Debug, Clone)]
+struct Shared {
+    packet_sender: tokio::sync::mpsc::Sender<Packet>,
+    delivery_tracker: DeliveryTracker,
+    capture: Option<Capture>,
+    nodes: NodeDirectory,
+    /// The modulation from the LoRa config of the radio.
+    modulation: Arc<Mutex<LoraModulation>>,
+}
+
+/// A connection to the radio and the task listening to it.
+#[derive(Debug)]
+struct Connection {
+    stream_api: meshtastic::api::ConnectedStreamApi,
     listener_task: tokio::task::JoinHandle<()>,
+    /// Whether `listener_task` was awaited already, a `JoinHandle` must not be polled again after that.
+    listener_joined: bool,
     exit_sender: tokio::sync::broadcast::Sender<()>,
 }
 
 impl MeshtasticApi {
+    /// Connect to the radio. Every received `FromRadio` is recorded to `capture` if set.
     pub async fn new(
-        serial_path: String,
+        transport: Transport,
         packet_sender: tokio::sync::mpsc::Sender<Packet>,
+        capture: Option<Capture>,
     ) -> Result<Self, error::Error> {
+        let shared = Shared {
+            packet_sender,
+            delivery_tracker: DeliveryTracker::default(),
+            capture,
+            nodes: NodeDirectory::default(),
+            modulation: Arc::default(),
+        };
+        let (connection, node_id) = Self::connect(&transport, shared.clone()).await?;
+
+        Ok(Self {
+            transport,
+            shared,
+            connection: Some(connection),
+            node_id,
+            router: BotRouter::new(node_id),
+        })
+    }
+
+    async fn connect(
+        transport: &Transport,
+        shared: Shared,
+    ) -> Result<(Connection, NodeId), error::Error> {
+        tokio::time::timeout(CONNECT_TIMEOUT, Self::try_connect(transport, shared))
+            .await
+            .map_err(|_| error::Error::ConnectTimeout(CONNECT_TIMEOUT.as_secs()))?
+    }
+
+    async fn try_connect(
+        transport: &Transport,
+        shared: Shared,
+    ) -> Result<(Connection, NodeId), error::Error> {
         let stream_api = meshtastic::api::StreamApi::new();
-        tracing::trace!("Creating serial stream...");
-        let stream_handle =
-            meshtastic::utils::stream::build_serial_stream(serial_path, None, None, None)?;
-        tracing::trace!("Serial stream created.");
-        let (decoded_listener, stream_api) = stream_api.connect(stream_handle).await;
+        tracing::trace!("Creating {} stream...", transport);
+        let (decoded_listener, stream_api) = match transport {
+            Transport::Serial(serial_path) => {
+                let stream_handle = meshtastic::utils::stream::build_serial_stream(
+                    serial_path.clone(),
+                    None,
+                    None,
+                    None,
+                )?;
+                stream_api.connect(stream_handle).await
+            }
+            Transport::Usb { vid, pid } => {
+                let serial_path = Transport::find_usb_serial_port(*vid, *pid)?
+                    .ok_or(error::Error::UsbDeviceNotFound(*vid, *pid))?;
+                tracing::debug!(
+                    "Found USB device {:04x}:{:04x} at {}",
+                    vid,
+                    pid,
+                    serial_path
+                );
+
+                let stream_handle =
+                    meshtastic::utils::stream::build_serial_stream(serial_path, None, None, None)?;
+                stream_api.connect(stream_handle).await
+            }
+            Transport::Tcp(address) => {
+                let stream_handle =
+                    meshtastic::utils::stream::build_tcp_stream(address.clone()).await?;
+                stream_api.connect(stream_handle).await
+            }
+            Transport::Replay { path, speed } => {
+                let stream_handle = meshtastic::api::StreamHandle::from_stream(
+                    capture::replay(path, *speed).await?,
+                );
+                stream_api.connect(stream_handle).await
+            }
+            #[cfg(feature = "fake")]
+            Transport::Fake(link) => {
+                let stream_handle = meshtastic::api::StreamHandle::from_stream(link.connect()?);
+                stream_api.connect(stream_handle).await
+            }
+        };
+        tracing::trace!("Stream created.");
 
-        let my_info_task = tokio::task::spawn(async { Self::wait_for_my_info(decoded_listener) });
+        let my_info_capture = shared.capture.clone();
+        let my_info_task =
+            tokio::task::spawn(Self::wait_for_my_info(decoded_listener, my_info_capture));
 
         let config_id = meshtastic::utils::generate_rand_id();
         let stream_api = stream_api.configure(config_id).await?;
 
-        let (my_node_info, decoded_listener) = my_info_task.await?.await;
+        let (my_node_info, decoded_listener) =
+            my_info_task.await?.ok_or(error::Error::MissingMyNodeInfo)?;
 
         let (exit_sender, mut rx) = tokio::sync::broadcast::channel(1);
         let listener_task = tokio::task::spawn(async move {
@@ -46,53 +169,128 @@ impl MeshtasticApi {
                 _ = rx.recv() => {
                     tracing::info!("Exiting listener...");
                 }
-                _ = Self::listener_task(decoded_listener, packet_sender) => {
+                _ = Self::listener_task(decoded_listener, shared) => {
                     tracing::error!("Meshtastic Listener closed unexpected.");
                 }
             }
         });
 
-        Ok(Self {
-            stream_api,
-            node_id: NodeId::from(my_node_info),
+        Ok((
+            Connection {
+                stream_api,
+                listener_task,
+                listener_joined: false,
+                exit_sender,
+            },
+            NodeId::from(my_node_info),
+        ))
+    }
 
-            listener_task,
-            exit_sender,
-        })
+    /// Tear down the current connection, if any, and connect again.
+    pub async fn reconnect(&mut self) -> Result<(), error::Error> {
+        if let Some(connection) = self.connection.take() {
+            connection.close().await;
+        };
+
+        let (connection, node_id) = Self::connect(&self.transport, self.shared.clone()).await?;
+
+        if *node_id != *self.node_id {
+            tracing::warn!(
+                "Reconnected to another radio: {} instead of {}",
+                *node_id,
+                *self.node_id
+            );
+        };
+
+        self.connection = Some(connection);
+        self.node_id = node_id;
+        self.router = BotRouter::new(node_id);
+
+        Ok(())
+    }
+
+    /// Wait until the connection to the radio is lost and tear it down.
+    ///
+    /// Never returns while disconnected.
+    pub async fn disconnected(&mut self) {
+        let Some(connection) = &mut self.connection else {
+            return std::future::pending().await;
+        };
+
+        if let Err(e) = (&mut connection.listener_task).await
+            && !e.is_cancelled()
+        {
+            tracing::error!("Meshtastic listener task shut down unexpectedly.");
+        };
+        connection.listener_joined = true;
+        tracing::error!("Meshtastic connection lost.");
+
+        if let Some(connection) = self.connection.take() {
+            connection.close().await;
+        };
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
     }
 
     async fn wait_for_my_info(
         mut listener: UnboundedReceiver<meshtastic::protobufs::FromRadio>,
-    ) -> (
+        capture: Option<Capture>,
+    ) -> Option<(
         meshtastic::protobufs::MyNodeInfo,
         UnboundedReceiver<meshtastic::protobufs::FromRadio>,
-    ) {
+    )> {
         while let Some(from_radio) = listener.recv().await {
-            if let Some(payload_variant) = from_radio.payload_variant {
-                match payload_variant {
-                    protobufs::from_radio::PayloadVariant::MyInfo(my_node_info) => {
-                        return (my_node_info, listener);
-                    }
-                    _ => {}
-                }
+            if let Some(capture) = &capture {
+                capture.record(&from_radio);
+            };
+
+            if let Some(protobufs::from_radio::PayloadVariant::MyInfo(my_node_info)) =
+                from_radio.payload_variant
+            {
+                return Some((my_node_info, listener));
             };
         }
 
-        panic!("Meshtastic connection lost: Failed to get `MyNodeInfo`.");
+        tracing::error!("Meshtastic connection lost: Failed to get `MyNodeInfo`.");
+        None
     }
 
     async fn listener_task(
         mut listener: UnboundedReceiver<meshtastic::protobufs::FromRadio>,
-        sender: tokio::sync::mpsc::Sender<Packet>,
+        shared: Shared,
     ) {
         while let Some(from_radio) = listener.recv().await {
+            if let Some(capture) = &shared.capture {
+                capture.record(&from_radio);
+            };
+
             if let Some(payload_variant) = from_radio.payload_variant {
                 match payload_variant {
                     protobufs::from_radio::PayloadVariant::Packet(mesh_packet) => {
-                        if let Err(_) = Self::handle_mesh_packet(mesh_packet, &sender).await {
+                        // Fails once the bot stopped receiving packets.
+                        let handled = Self::handle_mesh_packet(mesh_packet, &shared).await;
+                        if handled.is_err() {
                             return;
                         };
                     }
+                    protobufs::from_radio::PayloadVariant::NodeInfo(node_info) => {
+                        if let Some(user) = node_info.user {
+                            shared.nodes.update(node_info.num, user);
+                        };
+                    }
+                    protobufs::from_radio::PayloadVariant::Config(protobufs::Config {
+                        payload_variant: Some(protobufs::config::PayloadVariant::Lora(lora)),
+                        ..
+                    }) => {
+                        let modulation = LoraModulation::from(&lora);
+                        tracing::debug!("LoRa modulation: {:?}", modulation);
+                        *shared
+                            .modulation
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner) = modulation;
+                    }
                     _ => {}
                 }
             }
@@ -103,25 +301,32 @@ impl MeshtasticApi {
 
     async fn handle_mesh_packet(
         mesh_packet: meshtastic::protobufs::MeshPacket,
-        sender: &tokio::sync::mpsc::Sender<Packet>,
+        shared: &Shared,
     ) -> Result<(), ()> {
-        if let Some(variation) = &mesh_packet.payload_variant {
-            match &variation {
-                protobufs::mesh_packet::PayloadVariant::Decoded(data) => {
-                    tracing::debug!("Decoded Packet: {:?}", data);
-                    tracing::debug!("Payload: {}", String::from_utf8_lossy(&data.payload));
-
-                    if data.emoji == 0
-                        && let Err(_) = sender.send(Packet::new(&mesh_packet, data)).await
-                    {
-                        tracing::warn!(
-                            "All Meshtastic packet receivers have been closed. Meshtastic sender stopping..."
-                        );
-                        return Err(());
-                    };
-                }
-                _ => {}
+        let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) =
+            &mesh_packet.payload_variant
+        else {
+            return Ok(());
+        };
+
+        let packet = Packet::new(&mesh_packet, data);
+        tracing::debug!("Packet: {:?}", packet);
+
+        match &packet.payload {
+            packet::Payload::Routing(routing) => {
+                shared
+                    .delivery_tracker
+                    .resolve(data.request_id, mesh_packet.from, routing);
             }
+            packet::Payload::NodeInfo(user) => shared.nodes.update(mesh_packet.from, user.clone()),
+            _ => {}
+        };
+
+        if shared.packet_sender.send(packet).await.is_err() {
+            tracing::warn!(
+                "All Meshtastic packet receivers have been closed. Meshtastic sender stopping..."
+            );
+            return Err(());
         };
 
         Ok(())
@@ -129,16 +334,8 @@ impl MeshtasticApi {
 
     /// Disconnect from Meshtastic device.
     pub async fn disconnect(self) {
-        if self.exit_sender.send(()).is_err() {
-            tracing::warn!("All tasks have stopped already.");
-        };
-
-        if let Err(e) = self.stream_api.disconnect().await {
-            tracing::error!("Failed to disconnect from Meshtastic Device: {}", e);
-        };
-
-        if self.listener_task.await.is_err() {
-            tracing::error!("Meshtastic listener task shut down unexpectedly.");
+        if let Some(connection) = self.connection {
+            connection.close().await;
         };
     }
 
@@ -146,30 +343,160 @@ impl MeshtasticApi {
         self.node_id
     }
 
+    /// The known users of the nodes in the mesh.
+    pub fn nodes(&self) -> &NodeDirectory {
+        &self.shared.nodes
+    }
+
+    /// The estimated airtime of a packet with `payload_len` bytes of payload with the modulation of the radio.
+    pub fn airtime(&self, payload_len: usize) -> std::time::Duration {
+        self.shared
+            .modulation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .airtime(payload_len)
+    }
+
+    /// Send a text message and return the id of the sent packet.
+    ///
+    /// Clients show the message as a reply to the packet `reply_id` if set.
     pub async fn send_message(
-        &self,
+        &mut self,
         text: String,
         target: packet::Target,
         channel: Option<Channel>,
-    ) -> Result<(), error::SendError> {
+        reply_id: Option<u32>,
+    ) -> Result<u32, error::SendError> {
+        self.send_text_packet(text, target, channel, reply_id, false)
+            .await
+    }
+
+    /// React to the packet `reply_id` with `emoji`, like a tapback. Returns the id of the sent packet.
+    pub async fn send_reaction(
+        &mut self,
+        emoji: &str,
+        reply_id: u32,
+        target: packet::Target,
+        channel: Option<Channel>,
+    ) -> Result<u32, error::SendError> {
+        self.send_text_packet(emoji.to_string(), target, channel, Some(reply_id), true)
+            .await
+    }
+
+    async fn send_text_packet(
+        &mut self,
+        text: String,
+        target: packet::Target,
+        channel: Option<Channel>,
+        reply_id: Option<u32>,
+        emoji: bool,
+    ) -> Result<u32, error::SendError> {
         if text.len() > MAX_PAYLOAD_SIZE {
             return Err(error::SendError::TooBig(text.len()));
         };
 
-        match self
+        let Some(connection) = &mut self.connection else {
+            return Err(error::SendError::Disconnected);
+        };
+
+        connection
             .stream_api
-            .send_text(
-                packet_router,
-                text,
+            .send_mesh_packet(
+                &mut self.router,
+                meshtastic::types::EncodedMeshPacketData::new(text.into_bytes()),
+                protobufs::PortNum::TextMessageApp,
                 target.into(),
-                true,
                 channel.unwrap_or_default().into(),
+                // Reactions are not worth the airtime of an acknowledgement.
+                !emoji,
+                false,
+                true,
+                reply_id,
+                emoji.then_some(1),
             )
-            .await
-        {
-            _ => {}
+            .await?;
+
+        self.router
+            .take_last_sent_id()
+            .ok_or(error::SendError::MissingPacketId)
+    }
+
+    /// Send a text message with `want_ack` and track its delivery.
+    pub async fn send_message_tracked(
+        &mut self,
+        text: String,
+        target: packet::Target,
+        channel: Option<Channel>,
+        reply_id: Option<u32>,
+    ) -> Result<PendingDelivery, error::SendError> {
+        let destination = target.into_id();
+        let id = self.send_message(text, target, channel, reply_id).await?;
+
+        Ok(self.shared.delivery_tracker.track(id, destination))
+    }
+
+    /// Broadcast environment metrics as telemetry of this node, like a weather station would.
+    pub async fn send_environment_metrics(
+        &mut self,
+        metrics: protobufs::EnvironmentMetrics,
+        channel: Option<Channel>,
+    ) -> Result<(), error::SendError> {
+        use meshtastic::Message;
+
+        let Some(connection) = &mut self.connection else {
+            return Err(error::SendError::Disconnected);
+        };
+
+        let telemetry = protobufs::Telemetry {
+            time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|now| now.as_secs() as u32)
+                .unwrap_or_default(),
+            variant: Some(protobufs::telemetry::Variant::EnvironmentMetrics(metrics)),
+        };
+
+        let mesh_packet = protobufs::MeshPacket {
+            from: *self.node_id,
+            to: packet::Target::PRIMARY_CHANNEL_ID,
+            channel: channel.unwrap_or_default().into_channel() as u32,
+            id: meshtastic::utils::generate_rand_id(),
+            hop_limit: HOP_LIMIT,
+            payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+                protobufs::Data {
+                    portnum: protobufs::PortNum::TelemetryApp as i32,
+                    payload: telemetry.encode_to_vec(),
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
         };
 
+        connection
+            .stream_api
+            .send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
+                mesh_packet,
+            )))
+            .await?;
+
         Ok(())
     }
 }
+
+impl Connection {
+    async fn close(self) {
+        if self.exit_sender.send(()).is_err() {
+            tracing::warn!("All tasks have stopped already.");
+        };
+
+        if let Err(e) = self.stream_api.disconnect().await {
+            tracing::error!("Failed to disconnect from Meshtastic Device: {}", e);
+        };
+
+        if !self.listener_joined
+            && let Err(e) = self.listener_task.await
+            && !e.is_cancelled()
+        {
+            tracing::error!("Meshtastic listener task shut down unexpectedly.");
+        };
+    }
+}
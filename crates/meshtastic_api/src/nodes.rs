@@ -0,0 +1,41 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use meshtastic::protobufs;
+
+/// The users of the nodes in the mesh, from the node database of the radio and the node info packets.
+#[derive(Debug, Clone, Default)]
+pub struct NodeDirectory {
+    users: Arc<Mutex<HashMap<u32, protobufs::User>>>,
+}
+
+impl NodeDirectory {
+    pub(crate) fn update(&self, node: u32, user: protobufs::User) {
+        self.users
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(node, user);
+    }
+
+    pub fn short_name(&self, node: u32) -> Option<String> {
+        self.user(node)
+            .map(|user| user.short_name)
+            .filter(|short_name| !short_name.is_empty())
+    }
+
+    pub fn long_name(&self, node: u32) -> Option<String> {
+        self.user(node)
+            .map(|user| user.long_name)
+            .filter(|long_name| !long_name.is_empty())
+    }
+
+    fn user(&self, node: u32) -> Option<protobufs::User> {
+        self.users
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&node)
+            .cloned()
+    }
+}
@@ -20,7 +20,42 @@ pub struct Packet {
     /// The already traveled hops. (hop_start - hop_limit)
     pub hops_traveled: u8,
 
-    pub payload: String,
+    /// UNIX timestamp the radio received the packet at.
+    pub rx_time: u32,
+    /// Signal to noise ratio in dB.
+    pub rx_snr: f32,
+    /// Received signal strength in dBm.
+    pub rx_rssi: i32,
+    /// The sender asked for an acknowledgement.
+    pub want_ack: bool,
+    /// Encrypted with the public key of this node. Only possible for direct messages.
+    pub pki_encrypted: bool,
+    /// The last byte of the node id of the node that relayed the packet last.
+    pub relay_node: u8,
+    /// The id of the packet this packet replies to. 0 if it is no reply.
+    pub reply_id: u32,
+
+    pub payload: Payload,
+}
+
+/// The decoded payload by port number.
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Text(String),
+    /// An emoji reaction (tapback) to the packet `Packet::reply_id`.
+    Reaction(String),
+    /// A text message threaded as a reply to the packet `Packet::reply_id`.
+    Reply(String),
+    Position(meshtastic::protobufs::Position),
+    Telemetry(meshtastic::protobufs::Telemetry),
+    NodeInfo(meshtastic::protobufs::User),
+    Routing(meshtastic::protobufs::Routing),
+    Waypoint(meshtastic::protobufs::Waypoint),
+    /// A port this crate does not decode, or a payload that failed to decode.
+    Other {
+        portnum: i32,
+        bytes: Vec<u8>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -44,9 +79,91 @@ impl Packet {
 
             hop_start: mesh_packet.hop_start as u8,
             hop_limit: mesh_packet.hop_limit as u8,
-            hops_traveled: mesh_packet.hop_start as u8 - mesh_packet.hop_limit as u8,
+            hops_traveled: (mesh_packet.hop_start as u8)
+                .saturating_sub(mesh_packet.hop_limit as u8),
+
+            rx_time: mesh_packet.rx_time,
+            rx_snr: mesh_packet.rx_snr,
+            rx_rssi: mesh_packet.rx_rssi,
+            want_ack: mesh_packet.want_ack,
+            pki_encrypted: mesh_packet.pki_encrypted,
+            relay_node: mesh_packet.relay_node as u8,
+            reply_id: data.reply_id,
+
+            payload: Payload::decode(data),
+        }
+    }
+
+    /// The text of a text message or a reply.
+    pub fn text(&self) -> Option<&str> {
+        match &self.payload {
+            Payload::Text(text) | Payload::Reply(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The id of the packet this packet replies or reacts to.
+    pub fn replies_to(&self) -> Option<u32> {
+        (self.reply_id != 0).then_some(self.reply_id)
+    }
+
+    /// A direct message to `node_id`.
+    pub fn is_direct_to(&self, node_id: u32) -> bool {
+        matches!(self.to, Target::NodeId(to) if to == node_id)
+    }
+}
+
+impl Payload {
+    pub fn decode(data: &meshtastic::protobufs::Data) -> Self {
+        use meshtastic::{Message, protobufs::PortNum};
+
+        let bytes = data.payload.as_slice();
+        let decoded = match PortNum::try_from(data.portnum) {
+            Ok(PortNum::TextMessageApp) if data.emoji != 0 => {
+                return Self::Reaction(String::from_utf8_lossy(bytes).to_string());
+            }
+            Ok(PortNum::TextMessageApp) if data.reply_id != 0 => {
+                return Self::Reply(String::from_utf8_lossy(bytes).to_string());
+            }
+            Ok(PortNum::TextMessageApp) => {
+                return Self::Text(String::from_utf8_lossy(bytes).to_string());
+            }
+            Ok(PortNum::PositionApp) => Message::decode(bytes).map(Self::Position),
+            Ok(PortNum::TelemetryApp) => Message::decode(bytes).map(Self::Telemetry),
+            Ok(PortNum::NodeinfoApp) => Message::decode(bytes).map(Self::NodeInfo),
+            Ok(PortNum::RoutingApp) => Message::decode(bytes).map(Self::Routing),
+            Ok(PortNum::WaypointApp) => Message::decode(bytes).map(Self::Waypoint),
+            _ => return Self::other(data),
+        };
+
+        decoded.unwrap_or_else(|e| {
+            tracing::warn!(
+                "Failed to decode the payload of port {}: {}",
+                data.portnum,
+                e
+            );
+            Self::other(data)
+        })
+    }
+
+    fn other(data: &meshtastic::protobufs::Data) -> Self {
+        Self::Other {
+            portnum: data.portnum,
+            bytes: data.payload.clone(),
+        }
+    }
+
+    pub fn portnum(&self) -> i32 {
+        use meshtastic::protobufs::PortNum;
 
-            payload: String::from_utf8_lossy(&data.payload).to_string(),
+        match self {
+            Self::Text(_) | Self::Reaction(_) | Self::Reply(_) => PortNum::TextMessageApp as i32,
+            Self::Position(_) => PortNum::PositionApp as i32,
+            Self::Telemetry(_) => PortNum::TelemetryApp as i32,
+            Self::NodeInfo(_) => PortNum::NodeinfoApp as i32,
+            Self::Routing(_) => PortNum::RoutingApp as i32,
+            Self::Waypoint(_) => PortNum::WaypointApp as i32,
+            Self::Other { portnum, .. } => *portnum,
         }
     }
 }
@@ -57,7 +174,7 @@ impl Target {
     pub fn into_id(&self) -> u32 {
         match self {
             Self::PrimaryChannel => Self::PRIMARY_CHANNEL_ID,
-            Self::NodeId(id) => id.clone(),
+            Self::NodeId(id) => *id,
         }
     }
 }
@@ -82,3 +199,105 @@ impl From<Target> for meshtastic::packet::PacketDestination {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use meshtastic::{
+        Message,
+        protobufs::{self, PortNum},
+    };
+
+    use super::*;
+
+    fn text_data(text: &str) -> protobufs::Data {
+        protobufs::Data {
+            portnum: PortNum::TextMessageApp as i32,
+            payload: text.as_bytes().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn mesh_packet(to: u32) -> protobufs::MeshPacket {
+        protobufs::MeshPacket {
+            from: 0x42,
+            to,
+            id: 7,
+            hop_start: 3,
+            hop_limit: 1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decodes_text() {
+        let packet = Packet::new(&mesh_packet(0x1234), &text_data("wx"));
+
+        assert!(matches!(packet.payload, Payload::Text(ref text) if text == "wx"));
+        assert_eq!(packet.text(), Some("wx"));
+        assert_eq!(packet.replies_to(), None);
+        assert_eq!(packet.hops_traveled, 2);
+        assert!(packet.is_direct_to(0x1234));
+        assert!(!packet.is_direct_to(0x42));
+    }
+
+    #[test]
+    fn decodes_reaction() {
+        let data = protobufs::Data {
+            emoji: 1,
+            reply_id: 99,
+            ..text_data("👍")
+        };
+        let packet = Packet::new(&mesh_packet(Target::PRIMARY_CHANNEL_ID), &data);
+
+        assert!(matches!(packet.payload, Payload::Reaction(ref emoji) if emoji == "👍"));
+        assert_eq!(packet.text(), None);
+        assert_eq!(packet.replies_to(), Some(99));
+        assert!(!packet.is_direct_to(0x1234));
+        assert_eq!(packet.payload.portnum(), PortNum::TextMessageApp as i32);
+    }
+
+    #[test]
+    fn decodes_reply() {
+        let data = protobufs::Data {
+            reply_id: 99,
+            ..text_data("more")
+        };
+        let packet = Packet::new(&mesh_packet(0x1234), &data);
+
+        assert!(matches!(packet.payload, Payload::Reply(ref text) if text == "more"));
+        assert_eq!(packet.text(), Some("more"));
+        assert_eq!(packet.replies_to(), Some(99));
+    }
+
+    #[test]
+    fn decodes_by_port() {
+        let position = protobufs::Position {
+            latitude_i: Some(525_200_000),
+            ..Default::default()
+        };
+        let data = protobufs::Data {
+            portnum: PortNum::PositionApp as i32,
+            payload: position.encode_to_vec(),
+            ..Default::default()
+        };
+
+        let payload = Payload::decode(&data);
+
+        assert!(matches!(payload, Payload::Position(ref decoded) if *decoded == position));
+        assert_eq!(payload.portnum(), PortNum::PositionApp as i32);
+    }
+
+    #[test]
+    fn keeps_undecodable_payloads() {
+        let data = protobufs::Data {
+            portnum: PortNum::PositionApp as i32,
+            payload: vec![0xff, 0xff],
+            ..Default::default()
+        };
+
+        let payload = Payload::decode(&data);
+
+        assert!(matches!(payload, Payload::Other { portnum, ref bytes }
+            if portnum == PortNum::PositionApp as i32 && *bytes == data.payload));
+    }
+}
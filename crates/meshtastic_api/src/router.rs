@@ -0,0 +1,49 @@
+use std::convert::Infallible;
+
+use meshtastic::{packet::PacketRouter, protobufs};
+
+use crate::node_id::NodeId;
+
+/// Routes the packets sent by the bot's own node.
+///
+/// Incoming packets are handled by the listener task. The echo of a sent packet is recorded to learn its id.
+#[derive(Debug)]
+pub(crate) struct BotRouter {
+    node_id: NodeId,
+    /// The id of the last packet sent through this router.
+    last_sent_id: Option<u32>,
+}
+
+impl BotRouter {
+    pub(crate) fn new(node_id: NodeId) -> Self {
+        Self {
+            node_id,
+            last_sent_id: None,
+        }
+    }
+
+    /// Take the id of the last sent packet.
+    pub(crate) fn take_last_sent_id(&mut self) -> Option<u32> {
+        self.last_sent_id.take()
+    }
+}
+
+impl PacketRouter<(), Infallible> for BotRouter {
+    fn handle_packet_from_radio(
+        &mut self,
+        _packet: protobufs::FromRadio,
+    ) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn handle_mesh_packet(&mut self, packet: protobufs::MeshPacket) -> Result<(), Infallible> {
+        tracing::trace!("Sent packet {}", packet.id);
+        self.last_sent_id = Some(packet.id);
+
+        Ok(())
+    }
+
+    fn source_node_id(&self) -> meshtastic::types::NodeId {
+        meshtastic::types::NodeId::new(*self.node_id)
+    }
+}
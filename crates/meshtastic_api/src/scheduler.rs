@@ -0,0 +1,291 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// The priority of a queued send, highest last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Telemetry,
+    Subscription,
+    Reply,
+    Alert,
+}
+
+/// Queues sends by priority and releases them within a duty-cycle airtime budget.
+///
+/// The budget is `duty_cycle` of a sliding `window`, e.g. 1% of an hour for most of EU868.
+/// Within a priority sends are released in the order they were queued.
+#[derive(Debug)]
+pub struct Scheduler<T> {
+    queue: BTreeMap<(Priority, Reverse<i64>), Queued<T>>,
+    capacity: usize,
+    next_seq: i64,
+    /// The sequence number of the next requeued send, counting down so it sorts ahead of all others.
+    front_seq: i64,
+    duty_cycle: f32,
+    window: Duration,
+    /// The airtime spent within the window, oldest first.
+    spent: VecDeque<(Instant, Duration)>,
+}
+
+#[derive(Debug)]
+struct Queued<T> {
+    airtime: Duration,
+    item: T,
+}
+
+/// A send released by the scheduler.
+#[derive(Debug)]
+pub struct Scheduled<T> {
+    pub priority: Priority,
+    /// The estimated airtime, to report with `Scheduler::spend` once sent.
+    pub airtime: Duration,
+    pub item: T,
+}
+
+impl<T> Scheduler<T> {
+    /// `duty_cycle` is the share of the `window` the bot may transmit, 0.0 - 1.0.
+    pub fn new(duty_cycle: f32, window: Duration, capacity: usize) -> Self {
+        Self {
+            queue: BTreeMap::new(),
+            capacity,
+            next_seq: 0,
+            front_seq: -1,
+            duty_cycle,
+            window,
+            spent: VecDeque::new(),
+        }
+    }
+
+    /// Queue a send with its estimated `airtime`.
+    ///
+    /// When the queue is full the newest send of the lowest priority is dropped and returned.
+    pub fn push(&mut self, priority: Priority, airtime: Duration, item: T) -> Option<T> {
+        let key = (priority, Reverse(self.next_seq));
+        self.next_seq += 1;
+        self.queue.insert(key, Queued { airtime, item });
+
+        if self.queue.len() > self.capacity {
+            return self.queue.pop_first().map(|(_, queued)| queued.item);
+        };
+
+        None
+    }
+
+    /// Queue a send again that could not be sent, ahead of the sends of its priority.
+    pub fn requeue(&mut self, scheduled: Scheduled<T>) {
+        let key = (scheduled.priority, Reverse(self.front_seq));
+        self.front_seq -= 1;
+
+        self.queue.insert(
+            key,
+            Queued {
+                airtime: scheduled.airtime,
+                item: scheduled.item,
+            },
+        );
+    }
+
+    /// Wait until the next send fits into the airtime budget and take it.
+    ///
+    /// Never returns while the queue is empty. Cancel safe, nothing is taken before it returns.
+    pub async fn next(&mut self) -> Scheduled<T> {
+        let Some(airtime) = self
+            .queue
+            .last_key_value()
+            .map(|(_, queued)| queued.airtime)
+        else {
+            return std::future::pending().await;
+        };
+
+        if let Some(wait_until) = self.wait_until(airtime) {
+            tokio::time::sleep_until(wait_until).await;
+        };
+        self.expire();
+
+        let ((priority, _), queued) = self
+            .queue
+            .pop_last()
+            .expect("The queue is not empty, the send was not taken before the wait finished");
+
+        Scheduled {
+            priority,
+            airtime: queued.airtime,
+            item: queued.item,
+        }
+    }
+
+    /// Count the airtime of a send against the budget.
+    pub fn spend(&mut self, airtime: Duration) {
+        self.spent.push_back((Instant::now(), airtime));
+    }
+
+    /// The number of queued sends.
+    pub fn depth(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// The airtime spent within the current window.
+    pub fn airtime_spent(&self) -> Duration {
+        let window_start = Instant::now().checked_sub(self.window);
+        self.spent
+            .iter()
+            .filter(|(at, _)| window_start.is_none_or(|start| *at > start))
+            .map(|(_, airtime)| *airtime)
+            .sum()
+    }
+
+    /// The airtime allowed per window.
+    pub fn airtime_budget(&self) -> Duration {
+        self.window.mul_f32(self.duty_cycle)
+    }
+
+    /// When enough airtime expires for a send of `airtime`. `None` if it fits now.
+    ///
+    /// A send larger than the whole budget waits until nothing else was sent within the window.
+    fn wait_until(&self, airtime: Duration) -> Option<Instant> {
+        let budget = self.airtime_budget();
+        let now = Instant::now();
+        let mut spent = self.airtime_spent();
+
+        for (at, spent_airtime) in &self.spent {
+            let expires = *at + self.window;
+            if expires <= now {
+                continue;
+            };
+            if spent + airtime <= budget {
+                break;
+            };
+
+            spent = spent.saturating_sub(*spent_airtime);
+            if spent + airtime <= budget || spent.is_zero() {
+                return Some(expires);
+            };
+        }
+
+        None
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        while self
+            .spent
+            .front()
+            .is_some_and(|(at, _)| *at + self.window <= now)
+        {
+            self.spent.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler() -> Scheduler<&'static str> {
+        Scheduler::new(1.0, Duration::from_secs(3600), 8)
+    }
+
+    async fn take(scheduler: &mut Scheduler<&'static str>) -> &'static str {
+        scheduler.next().await.item
+    }
+
+    #[tokio::test]
+    async fn releases_by_priority_then_order() {
+        let mut scheduler = scheduler();
+        scheduler.push(Priority::Telemetry, Duration::ZERO, "telemetry");
+        scheduler.push(Priority::Reply, Duration::ZERO, "first reply");
+        scheduler.push(Priority::Reply, Duration::ZERO, "second reply");
+
+        assert_eq!(take(&mut scheduler).await, "first reply");
+        assert_eq!(take(&mut scheduler).await, "second reply");
+        assert_eq!(take(&mut scheduler).await, "telemetry");
+    }
+
+    #[tokio::test]
+    async fn requeue_into_empty_queue_keeps_later_pushes() {
+        let mut scheduler = scheduler();
+        scheduler.push(Priority::Reply, Duration::ZERO, "failed");
+        let failed = scheduler.next().await;
+
+        scheduler.requeue(failed);
+        scheduler.push(Priority::Reply, Duration::ZERO, "new");
+
+        assert_eq!(scheduler.depth(), 2);
+        assert_eq!(take(&mut scheduler).await, "failed");
+        assert_eq!(take(&mut scheduler).await, "new");
+    }
+
+    /// 10% of 100 s, a budget of 10 s.
+    fn budget_scheduler() -> Scheduler<&'static str> {
+        Scheduler::new(0.1, Duration::from_secs(100), 8)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn holds_sends_until_spent_airtime_expires() {
+        let mut scheduler = budget_scheduler();
+        let start = Instant::now();
+        scheduler.push(Priority::Reply, Duration::from_secs(6), "first");
+        scheduler.push(Priority::Reply, Duration::from_secs(6), "second");
+
+        let first = scheduler.next().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        scheduler.spend(first.airtime);
+
+        assert_eq!(take(&mut scheduler).await, "second");
+        assert_eq!(start.elapsed(), Duration::from_secs(100));
+        assert_eq!(scheduler.airtime_spent(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn releases_sends_that_fit_the_remaining_budget() {
+        let mut scheduler = budget_scheduler();
+        let start = Instant::now();
+        scheduler.spend(Duration::from_secs(3));
+        tokio::time::advance(Duration::from_secs(10)).await;
+        scheduler.spend(Duration::from_secs(3));
+        scheduler.push(Priority::Reply, Duration::from_secs(2), "fits");
+        scheduler.push(Priority::Reply, Duration::from_secs(3), "waits");
+
+        let fits = scheduler.next().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        scheduler.spend(fits.airtime);
+
+        // Only the airtime spent first has to expire, not all of it.
+        assert_eq!(take(&mut scheduler).await, "waits");
+        assert_eq!(start.elapsed(), Duration::from_secs(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_priorities_while_waiting_for_budget() {
+        let mut scheduler = budget_scheduler();
+        scheduler.spend(Duration::from_secs(10));
+        scheduler.push(Priority::Telemetry, Duration::from_secs(1), "telemetry");
+
+        // Cancelling the wait leaves the send queued.
+        let waited = tokio::time::timeout(Duration::from_secs(50), scheduler.next()).await;
+        assert!(waited.is_err());
+        assert_eq!(scheduler.depth(), 1);
+
+        scheduler.push(Priority::Alert, Duration::from_secs(1), "alert");
+        assert_eq!(take(&mut scheduler).await, "alert");
+        assert_eq!(take(&mut scheduler).await, "telemetry");
+    }
+
+    #[tokio::test]
+    async fn requeue_goes_ahead_of_its_priority() {
+        let mut scheduler = scheduler();
+        scheduler.push(Priority::Reply, Duration::ZERO, "failed");
+        scheduler.push(Priority::Reply, Duration::ZERO, "queued");
+        let failed = scheduler.next().await;
+
+        scheduler.requeue(failed);
+
+        assert_eq!(take(&mut scheduler).await, "failed");
+        assert_eq!(take(&mut scheduler).await, "queued");
+    }
+}
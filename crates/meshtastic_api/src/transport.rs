@@ -0,0 +1,108 @@
+/// How the bot is connected to its Meshtastic radio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    /// A radio attached over USB. The serial port path, e.g. `/dev/ttyUSB0`.
+    Serial(String),
+    /// A radio attached over USB, found by its vendor and product id.
+    ///
+    /// The port is looked up on every connect, so it may change when the radio is replugged.
+    Usb { vid: u16, pid: u16 },
+    /// A WiFi or Ethernet radio. The `host:port` address.
+    Tcp(String),
+    /// A capture of the radio stream, fed back at `speed` times the original pace. 0 replays as fast as possible.
+    Replay { path: String, speed: u32 },
+    /// An in-memory radio for tests.
+    #[cfg(feature = "fake")]
+    Fake(crate::fake::FakeLink),
+}
+
+impl Transport {
+    /// The port the Meshtastic firmware listens on for TCP clients.
+    pub const DEFAULT_TCP_PORT: u16 = 4403;
+
+    /// A TCP transport to `address`, with the default port if `address` has none.
+    ///
+    /// IPv6 addresses need brackets to carry a port, e.g. `[fe80::1]:4403`. Bare ones get bracketed.
+    pub fn tcp(address: impl Into<String>) -> Self {
+        let address = address.into();
+        // `host:port` has one colon, a bare IPv6 address at least two.
+        if address.matches(':').count() > 1 && !address.starts_with('[') {
+            return Self::Tcp(format!("[{}]:{}", address, Self::DEFAULT_TCP_PORT));
+        };
+
+        let has_port = address.rsplit_once(':').is_some_and(|(host, port)| {
+            (!host.contains(':') || host.ends_with(']')) && port.parse::<u16>().is_ok()
+        });
+
+        if has_port {
+            Self::Tcp(address)
+        } else {
+            Self::Tcp(format!("{}:{}", address, Self::DEFAULT_TCP_PORT))
+        }
+    }
+
+    /// The serial port of the first USB device with `vid` and `pid`.
+    pub(crate) fn find_usb_serial_port(
+        vid: u16,
+        pid: u16,
+    ) -> Result<Option<String>, tokio_serial::Error> {
+        Ok(tokio_serial::available_ports()?
+            .into_iter()
+            .find_map(|port| match port.port_type {
+                tokio_serial::SerialPortType::UsbPort(usb) if usb.vid == vid && usb.pid == pid => {
+                    Some(port.port_name)
+                }
+                _ => None,
+            }))
+    }
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Serial(path) => write!(f, "serial {}", path),
+            Self::Usb { vid, pid } => write!(f, "usb {:04x}:{:04x}", vid, pid),
+            Self::Tcp(address) => write!(f, "tcp {}", address),
+            Self::Replay { path, speed } => write!(f, "replay {} at {}x", path, speed),
+            #[cfg(feature = "fake")]
+            Self::Fake(_) => write!(f, "fake"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp_address(address: &str) -> String {
+        match Transport::tcp(address) {
+            Transport::Tcp(address) => address,
+            transport => panic!("Not a TCP transport: {}", transport),
+        }
+    }
+
+    #[test]
+    fn tcp_adds_default_port() {
+        assert_eq!(tcp_address("192.168.1.20"), "192.168.1.20:4403");
+        assert_eq!(tcp_address("meshtastic.local"), "meshtastic.local:4403");
+        assert_eq!(tcp_address("[fe80::1]"), "[fe80::1]:4403");
+    }
+
+    #[test]
+    fn tcp_keeps_port() {
+        assert_eq!(tcp_address("192.168.1.20:4000"), "192.168.1.20:4000");
+        assert_eq!(
+            tcp_address("meshtastic.local:4403"),
+            "meshtastic.local:4403"
+        );
+        assert_eq!(tcp_address("[fe80::1]:4000"), "[fe80::1]:4000");
+    }
+
+    #[test]
+    fn tcp_brackets_bare_ipv6() {
+        assert_eq!(tcp_address("fe80::1"), "[fe80::1]:4403");
+        assert_eq!(tcp_address("::1"), "[::1]:4403");
+        assert_eq!(tcp_address("2001:db8::4403"), "[2001:db8::4403]:4403");
+        assert_eq!(tcp_address("fe80::1%eth0"), "[fe80::1%eth0]:4403");
+    }
+}
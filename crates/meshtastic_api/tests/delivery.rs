@@ -0,0 +1,146 @@
+//! Delivery tracking against routing packets of a fake radio.
+
+use std::time::Duration;
+
+use meshtastic::protobufs::routing;
+use meshtastic_api::{
+    MeshtasticApi,
+    delivery::{Delivery, PendingDelivery, RoutingError},
+    fake::FakeRadio,
+    packet::{Packet, Target},
+};
+use tokio::sync::mpsc::Receiver;
+
+const BOT_NODE_NUM: u32 = 0x1234;
+const DESTINATION_NODE_NUM: u32 = 0x42;
+const RELAY_NODE_NUM: u32 = 0x77;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn connect(radio: &FakeRadio) -> (MeshtasticApi, Receiver<Packet>) {
+    let (packet_sender, packet_receiver) = tokio::sync::mpsc::channel(8);
+    let meshtastic_api = MeshtasticApi::new(radio.transport(), packet_sender, None)
+        .await
+        .unwrap();
+
+    (meshtastic_api, packet_receiver)
+}
+
+/// Send a tracked message to `target` and take it from the radio.
+async fn send(
+    meshtastic_api: &mut MeshtasticApi,
+    radio: &mut FakeRadio,
+    target: Target,
+) -> PendingDelivery {
+    let pending = meshtastic_api
+        .send_message_tracked(String::from("hello"), target, None, None)
+        .await
+        .unwrap();
+    let sent = radio.next_sent_packet().await.unwrap();
+    assert_eq!(sent.id, pending.id);
+
+    pending
+}
+
+#[tokio::test]
+async fn acknowledged_by_destination() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(
+        &mut meshtastic_api,
+        &mut radio,
+        Target::NodeId(DESTINATION_NODE_NUM),
+    )
+    .await;
+
+    radio.inject_routing(DESTINATION_NODE_NUM, pending.id, routing::Error::None);
+
+    let delivery = pending.outcome(TIMEOUT).await;
+    assert_eq!(delivery, Delivery::Acknowledged);
+    assert!(delivery.is_delivered());
+}
+
+#[tokio::test]
+async fn implicit_ack_of_direct_message_waits_for_destination() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(
+        &mut meshtastic_api,
+        &mut radio,
+        Target::NodeId(DESTINATION_NODE_NUM),
+    )
+    .await;
+
+    radio.inject_routing(RELAY_NODE_NUM, pending.id, routing::Error::None);
+    radio.inject_routing(DESTINATION_NODE_NUM, pending.id, routing::Error::None);
+
+    assert_eq!(pending.outcome(TIMEOUT).await, Delivery::Acknowledged);
+}
+
+#[tokio::test]
+async fn implicit_ack_alone_does_not_deliver_direct_message() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(
+        &mut meshtastic_api,
+        &mut radio,
+        Target::NodeId(DESTINATION_NODE_NUM),
+    )
+    .await;
+
+    radio.inject_routing(RELAY_NODE_NUM, pending.id, routing::Error::None);
+
+    let delivery = pending.outcome(Duration::from_millis(200)).await;
+    assert_eq!(delivery, Delivery::TimedOut);
+    assert!(!delivery.is_delivered());
+}
+
+#[tokio::test]
+async fn implicit_ack_delivers_broadcast() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(&mut meshtastic_api, &mut radio, Target::PrimaryChannel).await;
+
+    radio.inject_routing(RELAY_NODE_NUM, pending.id, routing::Error::None);
+
+    let delivery = pending.outcome(TIMEOUT).await;
+    assert_eq!(delivery, Delivery::Relayed);
+    assert!(delivery.is_delivered());
+}
+
+#[tokio::test]
+async fn nak_fails_delivery() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(
+        &mut meshtastic_api,
+        &mut radio,
+        Target::NodeId(DESTINATION_NODE_NUM),
+    )
+    .await;
+
+    radio.inject_routing(BOT_NODE_NUM, pending.id, routing::Error::MaxRetransmit);
+
+    let delivery = pending.outcome(TIMEOUT).await;
+    assert_eq!(
+        delivery,
+        Delivery::Failed(RoutingError(routing::Error::MaxRetransmit as i32))
+    );
+    assert!(!delivery.is_delivered());
+}
+
+#[tokio::test]
+async fn times_out_without_routing_packet() {
+    let mut radio = FakeRadio::new(BOT_NODE_NUM);
+    let (mut meshtastic_api, _packet_receiver) = connect(&radio).await;
+    let pending = send(
+        &mut meshtastic_api,
+        &mut radio,
+        Target::NodeId(DESTINATION_NODE_NUM),
+    )
+    .await;
+
+    assert_eq!(
+        pending.outcome(Duration::from_millis(200)).await,
+        Delivery::TimedOut
+    );
+}
@@ -0,0 +1,66 @@
+//! Replays of recorded radio streams from `captures/`.
+
+use meshtastic_api::{
+    MeshtasticApi,
+    packet::{Payload, Target},
+    transport::Transport,
+};
+
+fn capture_path(name: &str) -> String {
+    format!(
+        "{}/tests/captures/{}.capture",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    )
+}
+
+#[tokio::test]
+async fn replays_text_messages() {
+    let (packet_sender, mut packet_receiver) = tokio::sync::mpsc::channel(4);
+    let meshtastic_api = MeshtasticApi::new(
+        Transport::Replay {
+            path: capture_path("text_messages"),
+            speed: 0,
+        },
+        packet_sender,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(*meshtastic_api.get_node_id(), 0x1234);
+
+    let direct = packet_receiver.recv().await.unwrap();
+    assert_eq!(direct.from, 0x42);
+    assert!(matches!(direct.to, Target::NodeId(0x1234)));
+    assert_eq!(direct.id, 7);
+    assert!(matches!(direct.payload, Payload::Text(ref text) if text == "wx"));
+
+    let broadcast = packet_receiver.recv().await.unwrap();
+    assert!(matches!(broadcast.to, Target::PrimaryChannel));
+    assert_eq!(broadcast.id, 8);
+    assert!(matches!(broadcast.payload, Payload::Text(ref text) if text == "hello mesh"));
+
+    assert_eq!(
+        meshtastic_api.nodes().short_name(0x42).as_deref(),
+        Some("ALC")
+    );
+
+    meshtastic_api.disconnect().await;
+}
+
+#[tokio::test]
+async fn fails_without_capture() {
+    let (packet_sender, _packet_receiver) = tokio::sync::mpsc::channel(4);
+
+    let result = MeshtasticApi::new(
+        Transport::Replay {
+            path: capture_path("missing"),
+            speed: 0,
+        },
+        packet_sender,
+        None,
+    )
+    .await;
+
+    assert!(result.is_err());
+}
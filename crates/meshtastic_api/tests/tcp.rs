@@ -0,0 +1,214 @@
+//! The TCP transport against a local stub of a network connected radio speaking the framed protobuf stream.
+
+use meshtastic::{
+    Message,
+    protobufs::{self, FromRadio, ToRadio},
+};
+use meshtastic_api::{
+    MeshtasticApi,
+    packet::{Payload, Target},
+    transport::Transport,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::mpsc::UnboundedSender,
+};
+
+const FRAME_START: [u8; 2] = [0x94, 0xc3];
+const RADIO_NODE_NUM: u32 = 0x1234;
+const OTHER_NODE_NUM: u32 = 0x42;
+
+/// Answer the config request of the bot and send it a text message, report every `ToRadio` to `sent_sender`.
+async fn serve_radio(listener: TcpListener, sent_sender: UnboundedSender<ToRadio>) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let (mut reader, mut writer) = stream.into_split();
+
+    while let Some(to_radio) = read_frame(&mut reader).await {
+        if let Some(protobufs::to_radio::PayloadVariant::WantConfigId(config_id)) =
+            to_radio.payload_variant
+        {
+            for payload_variant in [
+                protobufs::from_radio::PayloadVariant::MyInfo(protobufs::MyNodeInfo {
+                    my_node_num: RADIO_NODE_NUM,
+                    ..Default::default()
+                }),
+                protobufs::from_radio::PayloadVariant::ConfigCompleteId(config_id),
+                protobufs::from_radio::PayloadVariant::Packet(protobufs::MeshPacket {
+                    from: OTHER_NODE_NUM,
+                    to: RADIO_NODE_NUM,
+                    id: 7,
+                    payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+                        protobufs::Data {
+                            portnum: protobufs::PortNum::TextMessageApp as i32,
+                            payload: b"ping".to_vec(),
+                            ..Default::default()
+                        },
+                    )),
+                    ..Default::default()
+                }),
+            ] {
+                let from_radio = FromRadio {
+                    id: 0,
+                    payload_variant: Some(payload_variant),
+                };
+                write_frame(&mut writer, &from_radio).await;
+            }
+        };
+
+        if sent_sender.send(to_radio).is_err() {
+            return;
+        };
+    }
+}
+
+async fn read_frame(reader: &mut tokio::net::tcp::OwnedReadHalf) -> Option<ToRadio> {
+    // Skip everything up to the frame start, e.g. the wake up bytes.
+    let mut previous = 0;
+    loop {
+        let byte = reader.read_u8().await.ok()?;
+        if [previous, byte] == FRAME_START {
+            break;
+        };
+        previous = byte;
+    }
+
+    let len = reader.read_u16().await.ok()?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await.ok()?;
+
+    ToRadio::decode(payload.as_slice()).ok()
+}
+
+async fn write_frame(writer: &mut tokio::net::tcp::OwnedWriteHalf, from_radio: &FromRadio) {
+    let payload = from_radio.encode_to_vec();
+    let mut frame = FRAME_START.to_vec();
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    writer.write_all(&frame).await.unwrap();
+}
+
+#[tokio::test]
+async fn exchanges_text_messages_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sent_sender, mut sent_receiver) = tokio::sync::mpsc::unbounded_channel();
+    tokio::task::spawn(serve_radio(listener, sent_sender));
+
+    let (packet_sender, mut packet_receiver) = tokio::sync::mpsc::channel(4);
+    let mut meshtastic_api = MeshtasticApi::new(
+        Transport::tcp(format!("127.0.0.1:{}", port)),
+        packet_sender,
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(*meshtastic_api.get_node_id(), RADIO_NODE_NUM);
+
+    let packet = packet_receiver.recv().await.unwrap();
+    assert_eq!(packet.from, OTHER_NODE_NUM);
+    assert!(matches!(packet.payload, Payload::Text(ref text) if text == "ping"));
+
+    meshtastic_api
+        .send_message(
+            String::from("pong"),
+            Target::NodeId(OTHER_NODE_NUM),
+            None,
+            Some(packet.id),
+        )
+        .await
+        .unwrap();
+
+    let mesh_packet = loop {
+        let to_radio = sent_receiver.recv().await.unwrap();
+        if let Some(protobufs::to_radio::PayloadVariant::Packet(mesh_packet)) =
+            to_radio.payload_variant
+        {
+            break mesh_packet;
+        };
+    };
+    let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) = mesh_packet.payload_variant
+    else {
+        panic!("The sent packet is not decoded");
+    };
+    assert_eq!(mesh_packet.to, OTHER_NODE_NUM);
+    assert_eq!(data.payload, b"pong");
+    assert_eq!(data.reply_id, 7);
+
+    meshtastic_api.disconnect().await;
+}
+
+#[tokio::test]
+async fn fails_without_radio() {
+    // Take a free port and close it again.
+    let port = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let (packet_sender, _packet_receiver) = tokio::sync::mpsc::channel(4);
+
+    let result = MeshtasticApi::new(
+        Transport::tcp(format!("127.0.0.1:{}", port)),
+        packet_sender,
+        None,
+    )
+    .await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn notices_lost_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sent_sender, _sent_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let radio = tokio::task::spawn(serve_radio(listener, sent_sender));
+
+    let (packet_sender, _packet_receiver) = tokio::sync::mpsc::channel(4);
+    let mut meshtastic_api = MeshtasticApi::new(
+        Transport::tcp(format!("127.0.0.1:{}", port)),
+        packet_sender,
+        None,
+    )
+    .await
+    .unwrap();
+
+    // Dropping the stream of the radio closes the connection.
+    radio.abort();
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        meshtastic_api.disconnected(),
+    )
+    .await
+    .unwrap();
+
+    assert!(!meshtastic_api.is_connected());
+}
+
+#[tokio::test(start_paused = true)]
+async fn times_out_when_radio_stays_silent() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // Accept the connection but never answer, like a radio that hangs.
+    tokio::task::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut drain = Vec::new();
+        let _ = stream.read_to_end(&mut drain).await;
+    });
+    let (packet_sender, _packet_receiver) = tokio::sync::mpsc::channel(4);
+
+    let result = MeshtasticApi::new(
+        Transport::tcp(format!("127.0.0.1:{}", port)),
+        packet_sender,
+        None,
+    )
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(meshtastic_api::error::Error::ConnectTimeout(_))
+    ));
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+use crate::forecast::Coordinates;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AirPollution {
+    /// Coordinates of the requested location.
+    pub coord: Coordinates,
+    /// The current value or the hourly forecast values.
+    pub list: Vec<AirPollutionSegment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AirPollutionSegment {
+    /// Time of data, unix, UTC
+    pub dt: u64,
+    /// The air quality index.
+    pub main: AirPollutionMain,
+    /// Concentrations of the pollutants.
+    pub components: Components,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AirPollutionMain {
+    /// Air Quality Index.
+    pub aqi: Aqi,
+}
+
+/// Pollutant concentrations in μg/m3.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Components {
+    /// Carbon monoxide.
+    pub co: f32,
+    /// Nitrogen monoxide.
+    pub no: f32,
+    /// Nitrogen dioxide.
+    pub no2: f32,
+    /// Ozone.
+    pub o3: f32,
+    /// Sulphur dioxide.
+    pub so2: f32,
+    /// Fine particles matter.
+    pub pm2_5: f32,
+    /// Coarse particulate matter.
+    pub pm10: f32,
+    /// Ammonia.
+    pub nh3: f32,
+}
+
+/// The air quality index category after the European scale.
+///
+/// OWM sends it as a number from 1 (Good) to 5 (Very Poor).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum Aqi {
+    Good = 1,
+    Fair = 2,
+    Moderate = 3,
+    Poor = 4,
+    VeryPoor = 5,
+}
+
+impl Aqi {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Good => "Good",
+            Self::Fair => "Fair",
+            Self::Moderate => "Moderate",
+            Self::Poor => "Poor",
+            Self::VeryPoor => "Very Poor",
+        }
+    }
+}
+
+impl TryFrom<u8> for Aqi {
+    type Error = crate::error::Error;
+
+    fn try_from(aqi: u8) -> Result<Self, Self::Error> {
+        match aqi {
+            1 => Ok(Self::Good),
+            2 => Ok(Self::Fair),
+            3 => Ok(Self::Moderate),
+            4 => Ok(Self::Poor),
+            5 => Ok(Self::VeryPoor),
+            invalid => Err(crate::error::Error::InvalidAqi(invalid)),
+        }
+    }
+}
+
+impl From<Aqi> for u8 {
+    fn from(aqi: Aqi) -> Self {
+        aqi as u8
+    }
+}
+
+impl std::fmt::Display for Aqi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
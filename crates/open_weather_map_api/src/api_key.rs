@@ -0,0 +1,112 @@
+/// The OWM API key.
+///
+/// `Debug` and `Display` are redacted so the key can not leak into logs or errors.
+#[derive(Clone)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    /// The name of the query parameter that carries the key.
+    pub(crate) const QUERY_PARAMETER: &str = "appid";
+    const REDACTED: &str = "<redacted>";
+
+    pub fn new(key: String) -> Self {
+        Self(key)
+    }
+
+    /// The plain key for building requests.
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// Replace the key in the query of `url`.
+    pub(crate) fn redact_url(url: &mut reqwest::Url) {
+        if !url
+            .query_pairs()
+            .any(|(key, _)| key == Self::QUERY_PARAMETER)
+        {
+            return;
+        };
+
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| {
+                if key == Self::QUERY_PARAMETER {
+                    (key.into_owned(), Self::REDACTED.to_string())
+                } else {
+                    (key.into_owned(), value.into_owned())
+                }
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ApiKey").field(&Self::REDACTED).finish()
+    }
+}
+
+impl std::fmt::Display for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(Self::REDACTED)
+    }
+}
+
+impl From<String> for ApiKey {
+    fn from(key: String) -> Self {
+        Self::new(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0123456789abcdef";
+
+    fn redacted(url: &str) -> String {
+        let mut url = reqwest::Url::parse(url).unwrap();
+        ApiKey::redact_url(&mut url);
+        url.to_string()
+    }
+
+    #[test]
+    fn formatting_hides_key() {
+        let key = ApiKey::new(KEY.to_string());
+
+        assert_eq!(format!("{:?}", key), "ApiKey(\"<redacted>\")");
+        assert_eq!(key.to_string(), "<redacted>");
+        assert_eq!(key.expose(), KEY);
+    }
+
+    #[test]
+    fn redacts_key_in_middle_of_query() {
+        assert_eq!(
+            redacted(&format!(
+                "https://api.openweathermap.org/data/2.5/forecast?lat=52.5&appid={KEY}&units=metric"
+            )),
+            "https://api.openweathermap.org/data/2.5/forecast?lat=52.5&appid=%3Credacted%3E&units=metric"
+        );
+    }
+
+    #[test]
+    fn redacts_key_at_end_of_query() {
+        assert_eq!(
+            redacted(&format!(
+                "https://api.openweathermap.org/data/2.5/forecast?lat=52.5&lon=13.4&appid={KEY}"
+            )),
+            "https://api.openweathermap.org/data/2.5/forecast?lat=52.5&lon=13.4&appid=%3Credacted%3E"
+        );
+    }
+
+    #[test]
+    fn keeps_url_without_key() {
+        for url in [
+            "https://api.openweathermap.org/data/2.5/forecast?lat=52.5&lon=13.4",
+            "https://api.openweathermap.org/data/2.5/forecast",
+        ] {
+            assert_eq!(redacted(url), url);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::{Mutex, PoisonError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Limits for the number of API calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub calls_per_minute: u32,
+    /// Calls per UTC day.
+    pub calls_per_day: u32,
+    /// When only this many daily calls are left, cached forecasts are served even if expired.
+    pub reserve: u32,
+    /// Where the counters are stored so they survive restarts.
+    pub state_path: Option<String>,
+}
+
+/// Counts the API calls and decides if another call may be made.
+#[derive(Debug)]
+pub(crate) struct Budget {
+    config: BudgetConfig,
+    counters: Mutex<Counters>,
+    /// Serializes writes of the counters so the latest ones are written last.
+    persist_lock: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct Counters {
+    /// Unix minute of the current minute window.
+    minute: i64,
+    minute_calls: u32,
+    /// Unix day of the current UTC day.
+    day: i64,
+    day_calls: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BudgetState {
+    Available,
+    /// Only the reserve is left. Prefer stale cached forecasts.
+    Low,
+    Exhausted,
+}
+
+/// The remaining calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetStatus {
+    pub remaining_minute: u32,
+    pub calls_per_minute: u32,
+    pub remaining_day: u32,
+    pub calls_per_day: u32,
+}
+
+impl Budget {
+    /// Create a budget and restore the counters from `state_path`.
+    pub(crate) fn new(config: BudgetConfig) -> Self {
+        let counters = config
+            .state_path
+            .as_ref()
+            .and_then(|path| match std::fs::read_to_string(path) {
+                Ok(counters) => serde_json::from_str(&counters)
+                    .inspect_err(|e| tracing::warn!("Budget: Failed to parse counters: {}", e))
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    tracing::warn!("Budget: Failed to read counters: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Self {
+            config,
+            counters: Mutex::new(counters),
+            persist_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn state(&self) -> BudgetState {
+        self.state_of(&mut self.counters())
+    }
+
+    fn state_of(&self, counters: &mut Counters) -> BudgetState {
+        counters.roll_over();
+
+        if counters.minute_calls >= self.config.calls_per_minute
+            || counters.day_calls >= self.config.calls_per_day
+        {
+            BudgetState::Exhausted
+        } else if self.config.calls_per_day.saturating_sub(counters.day_calls)
+            <= self.config.reserve
+        {
+            BudgetState::Low
+        } else {
+            BudgetState::Available
+        }
+    }
+
+    /// Take one call from the budget.
+    ///
+    /// Return `Error::BudgetExhausted` if there is no call left.
+    pub(crate) async fn acquire(&self) -> Result<(), Error> {
+        {
+            let mut counters = self.counters();
+            if self.state_of(&mut counters) == BudgetState::Exhausted {
+                return Err(Error::BudgetExhausted);
+            };
+
+            counters.minute_calls += 1;
+            counters.day_calls += 1;
+        }
+        self.persist().await;
+
+        Ok(())
+    }
+
+    pub(crate) fn status(&self) -> BudgetStatus {
+        let mut counters = self.counters();
+        counters.roll_over();
+
+        BudgetStatus {
+            remaining_minute: self
+                .config
+                .calls_per_minute
+                .saturating_sub(counters.minute_calls),
+            calls_per_minute: self.config.calls_per_minute,
+            remaining_day: self.config.calls_per_day.saturating_sub(counters.day_calls),
+            calls_per_day: self.config.calls_per_day,
+        }
+    }
+
+    /// Lock the counters. Must not be held across an await.
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn persist(&self) {
+        let Some(path) = &self.config.state_path else {
+            return;
+        };
+
+        let _persist_guard = self.persist_lock.lock().await;
+        let counters = self.counters().clone();
+
+        let counters = match serde_json::to_string(&counters) {
+            Ok(counters) => counters,
+            Err(e) => {
+                tracing::warn!("Budget: Failed to serialize counters: {}", e);
+                return;
+            }
+        };
+
+        // Write to a temporary file first so a crash never leaves half written counters.
+        let tmp_path = format!("{path}.tmp");
+        let result = match tokio::fs::write(&tmp_path, counters).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, path).await,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            tracing::warn!("Budget: Failed to persist counters: {}", e);
+        };
+    }
+}
+
+impl Counters {
+    /// Reset the counters when a new minute or day has started.
+    fn roll_over(&mut self) {
+        let now = chrono::Utc::now();
+
+        let minute = now.timestamp() / 60;
+        if self.minute != minute {
+            self.minute = minute;
+            self.minute_calls = 0;
+        };
+
+        let day = now.timestamp() / (60 * 60 * 24);
+        if self.day != day {
+            self.day = day;
+            self.day_calls = 0;
+        };
+    }
+}
+
+impl Default for BudgetConfig {
+    /// The limits of the free OWM plan.
+    fn default() -> Self {
+        Self {
+            calls_per_minute: 60,
+            calls_per_day: 1000,
+            reserve: 50,
+            state_path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(calls_per_minute: u32, calls_per_day: u32, reserve: u32) -> Budget {
+        Budget::new(BudgetConfig {
+            calls_per_minute,
+            calls_per_day,
+            reserve,
+            state_path: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn limits_calls_per_minute() {
+        let budget = budget(2, 100, 0);
+
+        budget.acquire().await.unwrap();
+        budget.acquire().await.unwrap();
+        assert!(matches!(
+            budget.acquire().await,
+            Err(Error::BudgetExhausted)
+        ));
+        assert_eq!(budget.status().remaining_minute, 0);
+        assert_eq!(budget.status().remaining_day, 98);
+    }
+
+    #[tokio::test]
+    async fn limits_calls_per_day() {
+        let budget = budget(100, 2, 0);
+
+        budget.acquire().await.unwrap();
+        budget.acquire().await.unwrap();
+        assert!(matches!(
+            budget.acquire().await,
+            Err(Error::BudgetExhausted)
+        ));
+        assert_eq!(budget.status().remaining_day, 0);
+    }
+
+    #[tokio::test]
+    async fn new_minute_and_day_reset_counters() {
+        let budget = budget(1, 1, 0);
+        budget.acquire().await.unwrap();
+        assert_eq!(budget.state(), BudgetState::Exhausted);
+
+        {
+            let mut counters = budget.counters();
+            counters.minute -= 1;
+            counters.day -= 1;
+        }
+
+        assert_eq!(budget.state(), BudgetState::Available);
+        budget.acquire().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reserve_makes_budget_low() {
+        let budget = budget(100, 4, 2);
+        assert_eq!(budget.state(), BudgetState::Available);
+
+        budget.acquire().await.unwrap();
+        assert_eq!(budget.state(), BudgetState::Available);
+
+        budget.acquire().await.unwrap();
+        assert_eq!(budget.state(), BudgetState::Low);
+
+        budget.acquire().await.unwrap();
+        assert_eq!(budget.state(), BudgetState::Low);
+
+        budget.acquire().await.unwrap();
+        assert_eq!(budget.state(), BudgetState::Exhausted);
+    }
+
+    #[tokio::test]
+    async fn restores_persisted_counters() {
+        let path = std::env::temp_dir().join(format!("owm-budget-{}.json", std::process::id()));
+        let config = BudgetConfig {
+            calls_per_minute: 100,
+            calls_per_day: 100,
+            reserve: 0,
+            state_path: Some(path.to_string_lossy().into_owned()),
+        };
+
+        let budget = Budget::new(config.clone());
+        budget.acquire().await.unwrap();
+        budget.acquire().await.unwrap();
+
+        let restored = Budget::new(config);
+        assert_eq!(restored.status().remaining_day, 98);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,19 +1,106 @@
-use std::{collections::HashMap, fmt::Debug, hash::Hash};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    ops::Deref,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
 
-use crate::{Latitude, Longitude};
+use serde::{Serialize, de::DeserializeOwned};
 
+use crate::{Latitude, Longitude, error::Error};
+
+mod grid;
+mod persist;
+mod single_flight;
+
+use grid::Grid;
+use persist::{CacheWriter, PersistedEntry};
+pub(crate) use single_flight::Joined;
+use single_flight::SingleFlight;
+
+/// A forecast cache by location.
+///
+/// `V` distinguishes variants of the same location, e.g. the units and language of a request.
 #[derive(Debug)]
-pub struct Cache<F>
+pub struct Cache<F, V = ()>
 where
     F: Debug + Clone,
+    V: Debug + Clone + Eq + Hash,
 {
-    cache: HashMap<CacheIndex, CacheEntry<F>>,
+    cache: HashMap<CacheIndex<V>, CacheEntry<F>>,
+    grid: Grid,
     ttl: chrono::TimeDelta,
     soft_cache_limit: usize,
+    capacity: usize,
+    max_stale_age: chrono::TimeDelta,
+    /// Counts every use of an entry to find the least recently used one.
+    clock: u64,
+    stats: CacheStats,
+    /// Writes every change to disk if persistence is enabled.
+    writer: Option<CacheWriter>,
+}
+
+/// A `Cache` shared between tasks.
+///
+/// Concurrent fetches of the same entry are coalesced with `join_flight`, every task gets the result of one fetch.
+#[derive(Debug)]
+pub(crate) struct SharedCache<F, V = ()>
+where
+    F: Debug + Clone,
+    V: Debug + Clone + Eq + Hash,
+{
+    cache: Mutex<Cache<F, V>>,
+    in_flight: SingleFlight<CacheIndex<V>, Result<MaybeStale<F>, Error>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// How long a cached forecast is served without fetching a new one.
+    pub ttl: chrono::TimeDelta,
+    /// The size of the grid cells in km. All locations within a cell share their cache entries.
+    pub grid_cell_km: f64,
+    /// Controls how often the cache gets cleaned.
+    ///
+    /// The cache cleans itself every time this value is hit.
+    pub soft_cache_limit: usize,
+    /// The maximum number of entries. The least recently used entry is evicted to make room.
+    pub capacity: usize,
+    /// How long an expired forecast is kept to be served when no new one can be fetched.
+    pub max_stale_age: chrono::TimeDelta,
+    /// The directory the caches are saved to so they survive restarts. `None` keeps them in memory only.
+    pub persist_dir: Option<PathBuf>,
+}
+
+/// Counters of a cache since startup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lookups answered with a valid entry.
+    pub hits: u64,
+    /// Lookups without a valid entry.
+    pub misses: u64,
+    /// Entries removed to stay within the capacity.
+    pub evictions: u64,
+    /// Expired entries served because no new forecast could be fetched.
+    pub stale_served: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+/// A forecast that may be an expired cached one.
+#[derive(Debug, Clone)]
+pub struct MaybeStale<F> {
+    pub forecast: F,
+    /// The age of an expired forecast served because no new one could be fetched.
+    ///
+    /// `None` if the forecast is fresh.
+    pub stale_age: Option<chrono::TimeDelta>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct CacheIndex(pub i16, pub i16);
+/// The row and column of a `Grid` cell and the variant.
+pub(crate) struct CacheIndex<V>(pub i32, pub i32, pub V);
 
 #[derive(Debug, PartialEq, PartialOrd)]
 struct CacheEntry<F>
@@ -21,51 +108,236 @@ where
     F: Debug + Clone,
 {
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// The `clock` of the cache at the last use.
+    pub last_used: u64,
     pub forecast: F,
 }
 
-impl<F> Cache<F>
+impl<F, V> Cache<F, V>
 where
-    F: Debug + Clone,
+    F: Debug + Clone + Serialize + DeserializeOwned,
+    V: Debug + Clone + Eq + Hash + Serialize + DeserializeOwned,
 {
-    pub fn new(ttl: chrono::TimeDelta, soft_cache_limit: usize) -> Self {
-        Self {
-            cache: HashMap::with_capacity(soft_cache_limit),
-            ttl,
-            soft_cache_limit,
-        }
+    /// Create a cache and load the persisted entries of the cache `name` if `persist_dir` is set.
+    ///
+    /// Must be called within a tokio runtime if persistence is enabled.
+    pub fn new(config: &CacheConfig, name: &str) -> Self {
+        let mut cache = Self {
+            cache: HashMap::with_capacity(config.soft_cache_limit),
+            grid: Grid::new(config.grid_cell_km),
+            ttl: config.ttl,
+            soft_cache_limit: config.soft_cache_limit,
+            capacity: config.capacity.max(1),
+            max_stale_age: config.max_stale_age,
+            clock: 0,
+            stats: CacheStats::default(),
+            writer: None,
+        };
+
+        if let Some(persist_dir) = &config.persist_dir {
+            if let Err(e) = std::fs::create_dir_all(persist_dir) {
+                tracing::warn!(
+                    "Cache: Failed to create {}: {}",
+                    persist_dir.to_string_lossy(),
+                    e
+                );
+            };
+
+            let path = persist_dir.join(format!("{}.json", name));
+            cache.restore(persist::load(&path));
+            cache.writer = Some(CacheWriter::new(path));
+        };
+
+        cache
     }
 
     /// Lookup a `Forecast` in `Cache`.
     ///
     /// This will return `None` if either there is no cached Forecast or if the forecast is expired.
-    pub fn lookup(&mut self, lat: Latitude, lon: Longitude) -> Option<F> {
-        let cache_index = CacheIndex::new(lat, lon);
-        if let Some(cache_entry) = self.cache.get(&cache_index) {
-            if self.is_timestamp_valid(&cache_entry.timestamp) {
-                return Some(cache_entry.forecast.clone());
-            } else {
-                let _ = self.cache.remove(&cache_index);
-            };
+    /// Expired forecasts are kept up to `max_stale_age`, see `lookup_stale`.
+    pub fn lookup(&mut self, lat: Latitude, lon: Longitude, variant: V) -> Option<F> {
+        let forecast = self.peek(lat, lon, variant);
+        if forecast.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
         };
 
-        None
+        forecast
+    }
+
+    /// Like `lookup`, but does not count as hit or miss.
+    ///
+    /// Use this to check again for an entry of a lookup that was already counted.
+    pub fn peek(&mut self, lat: Latitude, lon: Longitude, variant: V) -> Option<F> {
+        let cache_index = CacheIndex::new(&self.grid, lat, lon, variant);
+        if !self
+            .cache
+            .get(&cache_index)
+            .is_some_and(|cache_entry| self.is_timestamp_valid(&cache_entry.timestamp))
+        {
+            return None;
+        };
+
+        let clock = self.tick();
+        let cache_entry = self.cache.get_mut(&cache_index)?;
+        cache_entry.last_used = clock;
+
+        Some(cache_entry.forecast.clone())
+    }
+
+    /// Lookup a `Forecast` in `Cache` even if it is expired, as long as it is not older than `max_stale_age`.
+    ///
+    /// Use this as fallback when no new forecast can be fetched.
+    pub fn lookup_stale(
+        &mut self,
+        lat: Latitude,
+        lon: Longitude,
+        variant: V,
+    ) -> Option<MaybeStale<F>> {
+        let cache_index = CacheIndex::new(&self.grid, lat, lon, variant);
+        let clock = self.tick();
+        let cache_entry = self.cache.get_mut(&cache_index)?;
+        let age = chrono::Utc::now() - cache_entry.timestamp;
+
+        if age < self.max_stale_age {
+            cache_entry.last_used = clock;
+            self.stats.stale_served += 1;
+            Some(MaybeStale {
+                forecast: cache_entry.forecast.clone(),
+                stale_age: Some(age),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Whether `lookup_stale` would find a forecast. Does not count as served.
+    pub fn has_stale(&self, lat: Latitude, lon: Longitude, variant: V) -> bool {
+        let cache_index = CacheIndex::new(&self.grid, lat, lon, variant);
+        self.cache.get(&cache_index).is_some_and(|cache_entry| {
+            chrono::Utc::now() - cache_entry.timestamp < self.max_stale_age
+        })
     }
 
     /// Cache a `Forecast`.
     ///
-    /// This will set or replace a Forecast.
-    pub fn cache(&mut self, lat: Latitude, lon: Longitude, forecast: F) {
-        let cache_index = CacheIndex::new(lat, lon);
-        let _ = self.cache.insert(cache_index, CacheEntry::new(forecast));
+    /// This will set or replace a Forecast and evict the least recently used entries beyond the capacity.
+    pub fn cache(&mut self, lat: Latitude, lon: Longitude, variant: V, forecast: F) {
+        let cache_index = CacheIndex::new(&self.grid, lat, lon, variant);
+        let clock = self.tick();
+        let _ = self
+            .cache
+            .insert(cache_index, CacheEntry::new(forecast, clock));
 
         let _ = self.check_cleanup();
+        self.evict_to_capacity();
+        self.persist();
+    }
+
+    /// The center of the grid cell containing the location, e.g. to prefetch a whole cell at once.
+    pub fn cell_center(&self, lat: Latitude, lon: Longitude) -> (Latitude, Longitude) {
+        self.grid.center(self.grid.cell(lat, lon))
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            len: self.cache.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    /// Remove the least recently used entries until the cache is within its capacity.
+    fn evict_to_capacity(&mut self) {
+        while self.cache.len() > self.capacity {
+            let Some(lru_key) = self
+                .cache
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                return;
+            };
+
+            let _ = self.cache.remove(&lru_key);
+            self.stats.evictions += 1;
+        }
+    }
+
+    /// Insert persisted entries with their original timestamps and drop the ones too old to be served.
+    ///
+    /// Older entries count as less recently used.
+    fn restore(&mut self, mut entries: Vec<PersistedEntry<F, V>>) {
+        entries.sort_by_key(|entry| entry.timestamp_ms);
+
+        for entry in entries {
+            let Some(timestamp) = chrono::DateTime::from_timestamp_millis(entry.timestamp_ms)
+            else {
+                continue;
+            };
+
+            let clock = self.tick();
+            let _ = self.cache.insert(
+                CacheIndex::new(&self.grid, entry.lat, entry.lon, entry.variant),
+                CacheEntry {
+                    timestamp,
+                    last_used: clock,
+                    forecast: entry.forecast,
+                },
+            );
+        }
+
+        let cleaned = self.cleanup();
+        self.evict_to_capacity();
+        tracing::debug!(
+            "Cache: Restored {} entries, dropped {} outdated.",
+            self.cache.len(),
+            cleaned
+        );
+    }
+
+    /// Hand a snapshot of all entries to the writer.
+    fn persist(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+
+        let entries: Vec<PersistedEntry<&F, &V>> = self
+            .cache
+            .iter()
+            .map(|(index, entry)| {
+                let (lat, lon) = index.as_coords(&self.grid);
+                PersistedEntry {
+                    lat,
+                    lon,
+                    variant: &index.2,
+                    timestamp_ms: entry.timestamp.timestamp_millis(),
+                    forecast: &entry.forecast,
+                }
+            })
+            .collect();
+
+        match serde_json::to_string(&entries) {
+            Ok(snapshot) => writer.write(snapshot),
+            Err(e) => tracing::warn!("Cache: Failed to serialize snapshot: {}", e),
+        };
     }
 
     fn is_timestamp_valid(&self, timestamp: &chrono::DateTime<chrono::Utc>) -> bool {
         chrono::Utc::now() - timestamp < self.ttl
     }
 
+    /// Whether an entry is still worth keeping as stale fallback.
+    fn is_timestamp_retained(&self, timestamp: &chrono::DateTime<chrono::Utc>) -> bool {
+        chrono::Utc::now() - timestamp < self.max_stale_age.max(self.ttl)
+    }
+
     /// Check if the cache len greater or equals to the `soft_len_limit` and clean the cache if its the case.
     ///
     /// Return `Some<usize>` if the cleanup was performed and how many entries where cleaned.
@@ -82,13 +354,13 @@ where
 
     /// Clean the cache and return how many entries where cleaned.
     ///
-    /// Goes through all items and purges expired.
+    /// Goes through all items and purges the ones older than `max_stale_age`.
     pub fn cleanup(&mut self) -> usize {
-        let expired_keys: Vec<CacheIndex> = self
+        let expired_keys: Vec<CacheIndex<V>> = self
             .cache
             .iter()
             .filter_map(|(key, cached)| {
-                if !self.is_timestamp_valid(&cached.timestamp) {
+                if !self.is_timestamp_retained(&cached.timestamp) {
                     Some(key)
                 } else {
                     None
@@ -116,13 +388,56 @@ where
     }
 }
 
-impl CacheIndex {
-    fn new(lat: Latitude, lon: Longitude) -> CacheIndex {
-        Self((lat * 100.0) as i16, (lon * 100.0) as i16)
+impl<F, V> SharedCache<F, V>
+where
+    F: Debug + Clone + Serialize + DeserializeOwned,
+    V: Debug + Clone + Eq + Hash + Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(config: &CacheConfig, name: &str) -> Self {
+        Self {
+            cache: Mutex::new(Cache::new(config, name)),
+            in_flight: SingleFlight::new(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        self.lock().stats()
+    }
+
+    pub(crate) fn cell_center(&self, lat: Latitude, lon: Longitude) -> (Latitude, Longitude) {
+        self.lock().cell_center(lat, lon)
+    }
+
+    /// Lock the cache. Must not be held across an await.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Cache<F, V>> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    fn as_coords(&self) -> (Latitude, Longitude) {
-        (self.0 as Latitude / 100.0, self.1 as Longitude / 100.0)
+    /// Lead the fetch of the entry at this location and variant or wait for the result of the task leading it.
+    ///
+    /// The leader should lookup the cache again, the entry may have been fetched just before it joined.
+    pub(crate) async fn join_flight(
+        &self,
+        lat: Latitude,
+        lon: Longitude,
+        variant: V,
+    ) -> Joined<'_, CacheIndex<V>, Result<MaybeStale<F>, Error>> {
+        let grid = self.lock().grid;
+        self.in_flight
+            .join(CacheIndex::new(&grid, lat, lon, variant))
+            .await
+    }
+}
+
+impl<V> CacheIndex<V> {
+    fn new(grid: &Grid, lat: Latitude, lon: Longitude, variant: V) -> Self {
+        let (row, column) = grid.cell(lat, lon);
+        Self(row, column, variant)
+    }
+
+    /// The center of the cell.
+    fn as_coords(&self, grid: &Grid) -> (Latitude, Longitude) {
+        grid.center((self.0, self.1))
     }
 }
 
@@ -130,10 +445,133 @@ impl<F> CacheEntry<F>
 where
     F: Debug + Clone,
 {
-    pub fn new(forecast: F) -> Self {
+    pub fn new(forecast: F, last_used: u64) -> Self {
         Self {
             timestamp: chrono::Utc::now(),
+            last_used,
+            forecast,
+        }
+    }
+}
+
+impl<F> MaybeStale<F> {
+    pub fn fresh(forecast: F) -> Self {
+        Self {
             forecast,
+            stale_age: None,
         }
     }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale_age.is_some()
+    }
+}
+
+impl<F> Deref for MaybeStale<F> {
+    type Target = F;
+
+    fn deref(&self) -> &Self::Target {
+        &self.forecast
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Locations far enough apart to fall into different grid cells.
+    const BERLIN: (Latitude, Longitude) = (52.52, 13.405);
+    const HAMBURG: (Latitude, Longitude) = (53.55, 9.993);
+    const MUNICH: (Latitude, Longitude) = (48.137, 11.575);
+
+    fn cache(ttl: chrono::TimeDelta, capacity: usize) -> Cache<String> {
+        Cache::new(
+            &CacheConfig {
+                ttl,
+                grid_cell_km: 2.0,
+                soft_cache_limit: 32,
+                capacity,
+                max_stale_age: chrono::TimeDelta::days(2),
+                persist_dir: None,
+            },
+            "test",
+        )
+    }
+
+    fn insert(cache: &mut Cache<String>, (lat, lon): (Latitude, Longitude), forecast: &str) {
+        cache.cache(lat, lon, (), forecast.to_string());
+    }
+
+    fn lookup(cache: &mut Cache<String>, (lat, lon): (Latitude, Longitude)) -> Option<String> {
+        cache.lookup(lat, lon, ())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = cache(chrono::TimeDelta::hours(1), 2);
+        insert(&mut cache, BERLIN, "berlin");
+        insert(&mut cache, HAMBURG, "hamburg");
+
+        // Berlin is used more recently than Hamburg now.
+        assert_eq!(lookup(&mut cache, BERLIN).as_deref(), Some("berlin"));
+        insert(&mut cache, MUNICH, "munich");
+
+        assert_eq!(lookup(&mut cache, HAMBURG), None);
+        assert_eq!(lookup(&mut cache, BERLIN).as_deref(), Some("berlin"));
+        assert_eq!(lookup(&mut cache, MUNICH).as_deref(), Some("munich"));
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().len, 2);
+    }
+
+    #[test]
+    fn replacing_an_entry_does_not_evict() {
+        let mut cache = cache(chrono::TimeDelta::hours(1), 2);
+        insert(&mut cache, BERLIN, "berlin");
+        insert(&mut cache, HAMBURG, "hamburg");
+        insert(&mut cache, BERLIN, "berlin again");
+
+        assert_eq!(lookup(&mut cache, HAMBURG).as_deref(), Some("hamburg"));
+        assert_eq!(lookup(&mut cache, BERLIN).as_deref(), Some("berlin again"));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let mut cache = cache(chrono::TimeDelta::hours(1), 8);
+        assert_eq!(lookup(&mut cache, BERLIN), None);
+        insert(&mut cache, BERLIN, "berlin");
+        assert!(lookup(&mut cache, BERLIN).is_some());
+        assert!(lookup(&mut cache, BERLIN).is_some());
+        // A peek repeats a lookup that was already counted.
+        assert!(cache.peek(BERLIN.0, BERLIN.1, ()).is_some());
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                evictions: 0,
+                stale_served: 0,
+                len: 1,
+                capacity: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn counts_stale_served() {
+        let mut cache = cache(chrono::TimeDelta::zero(), 8);
+        insert(&mut cache, BERLIN, "berlin");
+
+        assert_eq!(lookup(&mut cache, BERLIN), None);
+        assert!(cache.has_stale(BERLIN.0, BERLIN.1, ()));
+        let stale = cache.lookup_stale(BERLIN.0, BERLIN.1, ()).unwrap();
+
+        assert!(stale.is_stale());
+        assert_eq!(stale.forecast, "berlin");
+        assert_eq!(cache.stats().stale_served, 1);
+        assert_eq!(cache.stats().misses, 1);
+        assert!(cache.lookup_stale(HAMBURG.0, HAMBURG.1, ()).is_none());
+        assert_eq!(cache.stats().stale_served, 1);
+    }
 }
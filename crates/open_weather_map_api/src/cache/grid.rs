@@ -0,0 +1,109 @@
+use crate::{Latitude, Longitude};
+
+/// Divides the earth into cells of roughly equal size so nearby locations share cache entries.
+///
+/// Rows are `cell_km` high. Each row is split into columns `cell_km` wide at the row's center,
+/// so cells do not shrink towards the poles. Cells start at the equator and the prime meridian,
+/// so no cell spans two hemispheres.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Grid {
+    cell_km: f64,
+}
+
+impl Grid {
+    const KM_PER_DEGREE_LAT: f64 = 111.32;
+    const MIN_CELL_KM: f64 = 0.1;
+
+    pub(super) fn new(cell_km: f64) -> Self {
+        Self {
+            cell_km: cell_km.max(Self::MIN_CELL_KM),
+        }
+    }
+
+    /// The row and column of the cell containing the location.
+    pub(super) fn cell(&self, lat: Latitude, lon: Longitude) -> (i32, i32) {
+        let row = (lat / self.row_height()).floor() as i32;
+        let column = (lon / self.column_width(row)).floor() as i32;
+
+        (row, column)
+    }
+
+    /// The center of a cell.
+    pub(super) fn center(&self, (row, column): (i32, i32)) -> (Latitude, Longitude) {
+        (
+            (row as Latitude + 0.5) * self.row_height(),
+            (column as Longitude + 0.5) * self.column_width(row),
+        )
+    }
+
+    /// The height of a row in degrees of latitude.
+    fn row_height(&self) -> f64 {
+        self.cell_km / Self::KM_PER_DEGREE_LAT
+    }
+
+    /// The width of a column of `row` in degrees of longitude.
+    fn column_width(&self, row: i32) -> f64 {
+        let lat = ((row as f64 + 0.5) * self.row_height()).clamp(-89.0, 89.0);
+
+        self.cell_km / (Self::KM_PER_DEGREE_LAT * lat.to_radians().cos())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nearby_points_share_cell() {
+        let grid = Grid::new(10.0);
+
+        assert_eq!(grid.cell(52.52, 13.40), grid.cell(52.53, 13.41));
+        assert_eq!(grid.cell(-33.87, 151.21), grid.cell(-33.88, 151.20));
+    }
+
+    #[test]
+    fn distant_points_get_different_cells() {
+        let grid = Grid::new(10.0);
+
+        assert_ne!(grid.cell(52.52, 13.40), grid.cell(52.52, 13.60));
+        assert_ne!(grid.cell(52.52, 13.40), grid.cell(52.72, 13.40));
+    }
+
+    #[test]
+    fn cells_do_not_cross_equator_or_prime_meridian() {
+        let grid = Grid::new(10.0);
+
+        assert_ne!(grid.cell(0.001, 10.0), grid.cell(-0.001, 10.0));
+        assert_ne!(grid.cell(10.0, 0.001), grid.cell(10.0, -0.001));
+        assert_ne!(grid.cell(0.001, 0.001), grid.cell(-0.001, -0.001));
+    }
+
+    #[test]
+    fn center_is_shared_by_cell_and_inside_it() {
+        let grid = Grid::new(10.0);
+        let points = [(52.52, 13.40), (52.53, 13.41), (52.55, 13.43)];
+        let cell = grid.cell(points[0].0, points[0].1);
+
+        for (lat, lon) in points {
+            assert_eq!(grid.cell(lat, lon), cell);
+            assert_eq!(grid.center(grid.cell(lat, lon)), grid.center(cell));
+        }
+
+        let (lat, lon) = grid.center(cell);
+        assert_eq!(grid.cell(lat, lon), cell);
+    }
+
+    #[test]
+    fn columns_keep_their_width_towards_the_poles() {
+        let grid = Grid::new(10.0);
+
+        // 0.1° of longitude is about 11 km at the equator but only 3.8 km at 70°.
+        assert_ne!(grid.cell(0.05, 10.0), grid.cell(0.05, 10.1));
+        assert_eq!(grid.cell(70.0, 10.0), grid.cell(70.0, 10.1));
+    }
+
+    #[test]
+    fn clamps_tiny_cells() {
+        assert_eq!(Grid::new(0.0), Grid::new(Grid::MIN_CELL_KM));
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{Latitude, Longitude};
+
+/// A cache entry on disk.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct PersistedEntry<F, V> {
+    /// The center of the cell, so entries survive a change of the grid resolution.
+    pub lat: Latitude,
+    pub lon: Longitude,
+    pub variant: V,
+    /// The time the forecast was fetched, unix milliseconds.
+    pub timestamp_ms: i64,
+    pub forecast: F,
+}
+
+/// Writes snapshots of a cache to disk in the background.
+///
+/// Only the latest snapshot is written if snapshots come in faster than they can be written.
+#[derive(Debug)]
+pub(super) struct CacheWriter {
+    sender: tokio::sync::watch::Sender<String>,
+}
+
+impl CacheWriter {
+    /// Spawn the writer task. Must be called within a tokio runtime.
+    pub(super) fn new(path: PathBuf) -> Self {
+        let (sender, receiver) = tokio::sync::watch::channel(String::new());
+        tokio::task::spawn(Self::writer_task(path, receiver));
+
+        Self { sender }
+    }
+
+    pub(super) fn write(&self, snapshot: String) {
+        self.sender.send_replace(snapshot);
+    }
+
+    async fn writer_task(path: PathBuf, mut receiver: tokio::sync::watch::Receiver<String>) {
+        let tmp_path = path.with_extension("json.tmp");
+
+        while receiver.changed().await.is_ok() {
+            let snapshot = receiver.borrow_and_update().clone();
+
+            // Write to a temporary file first so a crash never leaves a half written cache.
+            let result = match tokio::fs::write(&tmp_path, snapshot).await {
+                Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                tracing::warn!("Cache: Failed to write {}: {}", path.to_string_lossy(), e);
+            };
+        }
+    }
+}
+
+/// Read the entries of a persisted cache.
+///
+/// Returns no entries if the file does not exist or can not be read.
+pub(super) fn load<F, V>(path: &Path) -> Vec<PersistedEntry<F, V>>
+where
+    F: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let entries = match std::fs::read_to_string(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            tracing::warn!("Cache: Failed to read {}: {}", path.to_string_lossy(), e);
+            return Vec::new();
+        }
+    };
+
+    serde_json::from_str(&entries).unwrap_or_else(|e| {
+        tracing::warn!("Cache: Failed to parse {}: {}", path.to_string_lossy(), e);
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cache::{Cache, CacheConfig, CacheIndex};
+
+    const BERLIN: (Latitude, Longitude) = (52.52, 13.405);
+    const HAMBURG: (Latitude, Longitude) = (53.55, 9.993);
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("owm-persist-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(persist_dir: &Path) -> CacheConfig {
+        CacheConfig {
+            ttl: chrono::TimeDelta::hours(1),
+            grid_cell_km: 2.0,
+            soft_cache_limit: 32,
+            capacity: 8,
+            max_stale_age: chrono::TimeDelta::days(2),
+            persist_dir: Some(persist_dir.to_path_buf()),
+        }
+    }
+
+    /// Wait for the writer to save `count` entries.
+    async fn wait_for_entries(path: &Path, count: usize) {
+        for _ in 0..100 {
+            if load::<String, ()>(path).len() == count {
+                return;
+            };
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("{} entries were not written", count);
+    }
+
+    #[tokio::test]
+    async fn reloaded_entries_keep_fetch_time() {
+        let dir = temp_dir("round-trip");
+        let mut cache: Cache<String> = Cache::new(&config(&dir), "test");
+        cache.cache(BERLIN.0, BERLIN.1, (), "berlin".to_string());
+        cache.cache(HAMBURG.0, HAMBURG.1, (), "hamburg".to_string());
+
+        // Hamburg was fetched 2 hours ago and is expired by now.
+        let hamburg = CacheIndex::new(&cache.grid, HAMBURG.0, HAMBURG.1, ());
+        cache.cache.get_mut(&hamburg).unwrap().timestamp -= chrono::TimeDelta::hours(2);
+        cache.persist();
+        wait_for_entries(&dir.join("test.json"), 2).await;
+
+        let mut reloaded: Cache<String> = Cache::new(&config(&dir), "test");
+        assert_eq!(
+            reloaded.lookup(BERLIN.0, BERLIN.1, ()).as_deref(),
+            Some("berlin")
+        );
+        assert_eq!(reloaded.lookup(HAMBURG.0, HAMBURG.1, ()), None);
+
+        let stale = reloaded.lookup_stale(HAMBURG.0, HAMBURG.1, ()).unwrap();
+        assert_eq!(stale.forecast, "hamburg");
+        assert!(stale.stale_age.unwrap() >= chrono::TimeDelta::hours(2));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_file_loads_no_entries() {
+        let dir = temp_dir("missing");
+
+        assert!(load::<String, ()>(&dir.join("test.json")).is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_file_starts_empty_cache() {
+        let dir = temp_dir("corrupt");
+        std::fs::write(dir.join("test.json"), "[{\"lat\": 52.5, \"lon\":").unwrap();
+
+        let mut cache: Cache<String> = Cache::new(&config(&dir), "test");
+        assert_eq!(cache.stats().len, 0);
+
+        // The cache still works and replaces the corrupt file.
+        cache.cache(BERLIN.0, BERLIN.1, (), "berlin".to_string());
+        wait_for_entries(&dir.join("test.json"), 1).await;
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
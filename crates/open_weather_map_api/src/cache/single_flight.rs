@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+};
+
+use tokio::sync::watch;
+
+/// Coalesces concurrent work on the same key.
+///
+/// The first task to join a key leads and does the work, the others wait for its result.
+#[derive(Debug)]
+pub(super) struct SingleFlight<K, T> {
+    in_flight: Mutex<HashMap<K, watch::Receiver<Option<T>>>>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Joined<'a, K, T>
+where
+    K: Eq + Hash,
+{
+    /// No other task works on the key. Do the work and `land` the result.
+    Leader(Flight<'a, K, T>),
+    /// The result of the task that led.
+    Landed(T),
+}
+
+/// Held by the leader while working on a key.
+///
+/// Dropping it without landing, e.g. when the leader is cancelled, lets a waiting task take over.
+#[derive(Debug)]
+pub(crate) struct Flight<'a, K, T>
+where
+    K: Eq + Hash,
+{
+    single_flight: &'a SingleFlight<K, T>,
+    key: K,
+    sender: watch::Sender<Option<T>>,
+}
+
+impl<K, T> SingleFlight<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+{
+    pub(super) fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lead the work on `key` or wait for the result of the task leading it.
+    pub(super) async fn join(&self, key: K) -> Joined<'_, K, T> {
+        loop {
+            let mut receiver = {
+                let mut in_flight = self
+                    .in_flight
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+
+                match in_flight.get(&key) {
+                    Some(receiver) => receiver.clone(),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        let _ = in_flight.insert(key.clone(), receiver);
+
+                        return Joined::Leader(Flight {
+                            single_flight: self,
+                            key,
+                            sender,
+                        });
+                    }
+                }
+            };
+
+            // Fails if the leader was dropped without landing, then try to lead.
+            if let Ok(result) = receiver.wait_for(Option::is_some).await
+                && let Some(result) = result.clone()
+            {
+                return Joined::Landed(result);
+            };
+        }
+    }
+}
+
+impl<K, T> Flight<'_, K, T>
+where
+    K: Eq + Hash,
+{
+    /// Hand `result` to every task waiting for it.
+    pub(crate) fn land(self, result: T) {
+        let _ = self.sender.send(Some(result));
+    }
+}
+
+impl<K, T> Drop for Flight<'_, K, T>
+where
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        // Tasks joining from now on lead a new flight, the waiting ones keep their receivers.
+        let _ = self
+            .single_flight
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.key);
+    }
+}
@@ -1,14 +1,58 @@
+use std::sync::Arc;
+
 type InputTimePeriod = u8;
 type MaxTimePeriod = u8;
 
-#[derive(Debug, thiserror::Error)]
+/// Cheap to clone, so the result of a fetch can be shared with all tasks waiting for it.
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum Error {
+    /// The API key is redacted from the URL of the error.
     #[error("Reqwest Error: {0}")]
-    Reqwest(#[from] reqwest::Error),
+    Reqwest(Arc<reqwest::Error>),
     #[error("Status Code: {0}")]
     StatusCode(reqwest::StatusCode),
+    /// The server answered with 401. This will not resolve itself.
+    #[error("Invalid API Key: Check the configured OWM API key")]
+    InvalidApiKey,
+    /// The server answered with 429 and maybe sent when to try again.
+    #[error("Rate Limited: Retry after {0:?}")]
+    RateLimited(Option<std::time::Duration>),
     #[error("Json Error: {}", 0)]
-    Json(#[from] serde_json::Error),
+    Json(Arc<serde_json::Error>),
     #[error("Time Period too long: {0} is too long: consider {1} at most.")]
     TooManyRequested(InputTimePeriod, MaxTimePeriod),
+    #[error("Invalid Air Quality Index: {0} is not in range 1..=5")]
+    InvalidAqi(u8),
+    #[error("Invalid Units: {0}: consider metric, imperial or standard")]
+    InvalidUnits(String),
+    #[error("Request Budget Exhausted: No API calls left for now")]
+    BudgetExhausted,
+}
+
+impl Error {
+    /// Errors caused by the configuration that need manual intervention.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::InvalidApiKey)
+    }
+
+    /// The server could not be reached or did not answer in time.
+    pub fn is_unreachable(&self) -> bool {
+        matches!(self, Self::Reqwest(e) if e.is_timeout() || e.is_connect())
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(mut e: reqwest::Error) -> Self {
+        if let Some(url) = e.url_mut() {
+            crate::api_key::ApiKey::redact_url(url);
+        };
+
+        Self::Reqwest(Arc::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(Arc::new(e))
+    }
 }
@@ -0,0 +1,232 @@
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api_key::ApiKey,
+    budget::{Budget, BudgetConfig, BudgetState, BudgetStatus},
+    error::Error,
+    retry::RetryConfig,
+};
+
+/// Settings of the HTTP client used for all requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// The OWM API host. Can be pointed at a local mock server.
+    pub base_url: String,
+    /// Timeout of a whole request in seconds.
+    pub timeout_s: u32,
+    /// Timeout of the connect phase in seconds.
+    pub connect_timeout_s: u32,
+    pub user_agent: String,
+    /// Proxy URL for all requests. E.g. `http://proxy.local:3128` or `socks5://127.0.0.1:1080`.
+    pub proxy: Option<String>,
+    pub retry: RetryConfig,
+    /// How long in seconds OWM counts as unreachable after a connect error or timeout.
+    ///
+    /// Meanwhile cached forecasts are served expired instead of waiting for requests that likely time out again.
+    pub unreachable_backoff_s: u32,
+}
+
+/// Sends the authenticated requests within the budget.
+#[derive(Debug)]
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: ApiKey,
+    budget: Budget,
+    retry: RetryConfig,
+    unreachable_backoff: std::time::Duration,
+    /// Set after a connect error or timeout, cleared by the next successful request.
+    unreachable_until: Mutex<Option<Instant>>,
+}
+
+impl HttpConfig {
+    fn build_client(&self) -> Result<reqwest::Client, Error> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(self.timeout_s as u64))
+            .connect_timeout(std::time::Duration::from_secs(
+                self.connect_timeout_s as u64,
+            ))
+            .user_agent(&self.user_agent);
+
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        };
+
+        Ok(builder.build()?)
+    }
+
+    /// The base url without a trailing slash.
+    fn base_url(&self) -> &str {
+        self.base_url.trim_end_matches('/')
+    }
+}
+
+impl HttpClient {
+    pub(crate) fn new(
+        api_key: ApiKey,
+        http_config: &HttpConfig,
+        budget_config: BudgetConfig,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            client: http_config.build_client()?,
+            base_url: http_config.base_url().to_string(),
+            api_key,
+            budget: Budget::new(budget_config),
+            retry: http_config.retry.clone(),
+            unreachable_backoff: std::time::Duration::from_secs(
+                http_config.unreachable_backoff_s as u64,
+            ),
+            unreachable_until: Mutex::new(None),
+        })
+    }
+
+    pub(crate) fn budget_state(&self) -> BudgetState {
+        self.budget.state()
+    }
+
+    pub(crate) fn budget_status(&self) -> BudgetStatus {
+        self.budget.status()
+    }
+
+    /// Whether a recent request failed to connect or timed out, see `HttpConfig::unreachable_backoff_s`.
+    pub(crate) fn is_unreachable(&self) -> bool {
+        self.lock_unreachable_until()
+            .is_some_and(|unreachable_until| Instant::now() < unreachable_until)
+    }
+
+    /// Send a GET request to `path` with `query` and the API key and return the response body.
+    ///
+    /// Failed requests are retried after the `RetryConfig`. Every attempt is taken from the budget.
+    pub(crate) async fn get(&self, path: &str, query: &str) -> Result<String, Error> {
+        self.request(path, query, true).await
+    }
+
+    /// Like `get`, but connect errors and timeouts fail at once instead of being retried.
+    ///
+    /// For callers with a fallback, so an unreachable OWM does not block them for the whole retry chain.
+    pub(crate) async fn get_or_fail_fast(&self, path: &str, query: &str) -> Result<String, Error> {
+        self.request(path, query, false).await
+    }
+
+    async fn request(
+        &self,
+        path: &str,
+        query: &str,
+        retry_unreachable: bool,
+    ) -> Result<String, Error> {
+        let url = format!(
+            "{}{}?{}&{}={}",
+            self.base_url,
+            path,
+            query,
+            ApiKey::QUERY_PARAMETER,
+            self.api_key.expose()
+        );
+        let mut retry = 0;
+
+        loop {
+            self.budget.acquire().await?;
+
+            let error = match self.client.get(&url).send().await {
+                Ok(response) => match Self::handle_status_code(&response) {
+                    Ok(()) => match response.text().await {
+                        Ok(text) => {
+                            *self.lock_unreachable_until() = None;
+                            return Ok(text);
+                        }
+                        Err(e) => Error::from(e),
+                    },
+                    Err(e) => e,
+                },
+                Err(e) => Error::from(e),
+            };
+
+            let unreachable = error.is_unreachable();
+            if unreachable {
+                *self.lock_unreachable_until() = Some(Instant::now() + self.unreachable_backoff);
+            };
+
+            let Some(backoff) = self
+                .retry_backoff(&error, retry)
+                .filter(|_| retry_unreachable || !unreachable)
+            else {
+                return Err(error);
+            };
+
+            retry += 1;
+            tracing::warn!(
+                "Request failed: {}: Retry {} of {} in {:?}",
+                error,
+                retry,
+                self.retry.max_retries,
+                backoff
+            );
+            tokio::time::sleep(backoff).await;
+        }
+    }
+
+    /// The backoff before the next retry or `None` if `error` should not be retried.
+    fn retry_backoff(&self, error: &Error, retry: u32) -> Option<std::time::Duration> {
+        if retry >= self.retry.max_retries {
+            return None;
+        };
+
+        match error {
+            error if error.is_unreachable() => Some(self.retry.backoff(retry)),
+            Error::StatusCode(status) if status.is_server_error() => {
+                Some(self.retry.backoff(retry))
+            }
+            Error::RateLimited(Some(retry_after)) => self.retry.within_max(*retry_after),
+            Error::RateLimited(None) => Some(self.retry.backoff(retry)),
+            _ => None,
+        }
+    }
+
+    fn lock_unreachable_until(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.unreachable_until
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn handle_status_code(response: &reqwest::Response) -> Result<(), Error> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        };
+
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED => Err(Error::InvalidApiKey),
+            reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|retry_after| retry_after.to_str().ok())
+                    .and_then(|retry_after| retry_after.trim().parse().ok())
+                    .map(std::time::Duration::from_secs);
+
+                Err(Error::RateLimited(retry_after))
+            }
+            status => Err(Error::StatusCode(status)),
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            base_url: String::from("https://api.openweathermap.org"),
+            timeout_s: 30,
+            connect_timeout_s: 10,
+            user_agent: format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            proxy: None,
+            retry: RetryConfig::default(),
+            unreachable_backoff_s: 60,
+        }
+    }
+}
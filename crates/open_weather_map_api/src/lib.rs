@@ -1,114 +1,325 @@
+use std::{fmt::Debug, hash::Hash};
+
 use chrono::Duration;
+use serde::{Serialize, de::DeserializeOwned};
 use tracing::instrument;
 
-use crate::{cache::Cache, error::Error, forecast::Forecast};
+use crate::{
+    air_pollution::AirPollution,
+    api_key::ApiKey,
+    budget::{BudgetConfig, BudgetState, BudgetStatus},
+    cache::{Joined, SharedCache},
+    error::Error,
+    forecast::Forecast,
+    http::{HttpClient, HttpConfig},
+    localization::Localization,
+    one_call::OneCall,
+};
 
 mod cache;
 
+pub mod air_pollution;
+pub mod api_key;
+pub mod budget;
 pub mod error;
 pub mod forecast;
+pub mod http;
+pub mod localization;
+pub mod one_call;
+pub mod retry;
+
+pub use cache::{CacheConfig, CacheStats, MaybeStale};
 
 type Latitude = f64;
 type Longitude = f64;
 
 #[derive(Debug)]
 pub struct OwmApi {
-    api_key: String,
-    cache: Cache<Forecast>,
+    http: HttpClient,
+    /// Forecasts by localization and requested count.
+    cache: SharedCache<Forecast, (Localization, Option<u8>)>,
+    air_pollution_cache: SharedCache<AirPollution>,
+    one_call_cache: SharedCache<OneCall, Localization>,
 }
 
 impl OwmApi {
-    pub fn new(api_key: String, cache_expiry: Duration, soft_cache_limit: usize) -> Self {
-        Self {
-            api_key,
-            cache: Cache::new(cache_expiry, soft_cache_limit),
-        }
+    /// One Call data is updated every 10 minutes and the minutely forecast is useless when older.
+    const ONE_CALL_CACHE_TTL_MIN: i64 = 10;
+
+    pub fn new(
+        api_key: ApiKey,
+        cache_config: &CacheConfig,
+        http_config: &HttpConfig,
+        budget_config: BudgetConfig,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            http: HttpClient::new(api_key, http_config, budget_config)?,
+            cache: SharedCache::new(cache_config, "forecast"),
+            air_pollution_cache: SharedCache::new(cache_config, "air_pollution_forecast"),
+            one_call_cache: SharedCache::new(
+                &CacheConfig {
+                    ttl: Duration::minutes(Self::ONE_CALL_CACHE_TTL_MIN),
+                    ..cache_config.clone()
+                },
+                "one_call",
+            ),
+        })
     }
 
-    #[instrument]
+    #[instrument(skip(self))]
     pub async fn get_5day_3hour_forecast(
-        &mut self,
+        &self,
         lat: Latitude,
         lon: Longitude,
         count: Option<u8>,
-    ) -> Result<Forecast, Error> {
+        localization: &Localization,
+    ) -> Result<MaybeStale<Forecast>, Error> {
         const MAX_REQUESTABLE: u8 = (24 / 3) * 5; // (24 hours / 3 hours) * 5 days: This calculates the max count.
 
-        let forecast = if let Some(forecast_hit) = self.cache.lookup(lat, lon) {
-            forecast_hit
-        } else {
-            let mut url = format!(
-                "https://api.openweathermap.org/data/2.5/forecast?lat={}&lon={}&appid={}&units=metric&lang=de",
-                lat, lon, self.api_key
-            );
-
-            if let Some(count) = count {
-                if count > MAX_REQUESTABLE {
-                    return Err(Error::TooManyRequested(count, MAX_REQUESTABLE));
-                };
+        let mut query = format!("lat={}&lon={}&{}", lat, lon, localization.as_query());
 
-                url.push_str(&format!("&cnt={}", count));
+        if let Some(count) = count {
+            if count > MAX_REQUESTABLE {
+                return Err(Error::TooManyRequested(count, MAX_REQUESTABLE));
             };
 
-            let response = reqwest::get(url).await?;
-            Self::handle_status_code(&response)?;
-            let response_text = response.text().await?;
+            query.push_str(&format!("&cnt={}", count));
+        };
 
-            tracing::debug!(
-                "5 Day 3 Hour Response at Lat: {}, Lon: {}: {}",
-                lat,
-                lon,
-                response_text
-            );
+        Self::cached_get(
+            &self.http,
+            &self.cache,
+            (lat, lon, (localization.clone(), count)),
+            "/data/2.5/forecast",
+            &query,
+        )
+        .await
+    }
+
+    /// Get the One Call 3.0 weather data with current weather, minutely, hourly and daily forecasts and alerts.
+    ///
+    /// Requires a "One Call by Call" subscription.
+    pub async fn get_one_call(
+        &self,
+        lat: Latitude,
+        lon: Longitude,
+        localization: &Localization,
+    ) -> Result<MaybeStale<OneCall>, Error> {
+        Self::cached_get(
+            &self.http,
+            &self.one_call_cache,
+            (lat, lon, localization.clone()),
+            "/data/3.0/onecall",
+            &format!("lat={}&lon={}&{}", lat, lon, localization.as_query()),
+        )
+        .await
+    }
 
-            let forecast: Forecast = serde_json::from_str::<Forecast>(&response_text)?.into();
-            self.cache.cache(lat, lon, forecast.clone());
+    /// Get the current air pollution at a location.
+    ///
+    /// This is not cached as it is only valid for the current hour.
+    pub async fn get_current_air_pollution(
+        &self,
+        lat: Latitude,
+        lon: Longitude,
+    ) -> Result<AirPollution, Error> {
+        let response_text = self
+            .http
+            .get(
+                "/data/2.5/air_pollution",
+                &format!("lat={}&lon={}", lat, lon),
+            )
+            .await?;
 
-            forecast
-        };
+        tracing::debug!(
+            "Current Air Pollution Response at Lat: {}, Lon: {}: {}",
+            lat,
+            lon,
+            response_text
+        );
+
+        Ok(serde_json::from_str::<AirPollution>(&response_text)?)
+    }
 
-        Ok(forecast)
+    /// Get the hourly air pollution forecast for the next 4 days at a location.
+    pub async fn get_air_pollution_forecast(
+        &self,
+        lat: Latitude,
+        lon: Longitude,
+    ) -> Result<MaybeStale<AirPollution>, Error> {
+        Self::cached_get(
+            &self.http,
+            &self.air_pollution_cache,
+            (lat, lon, ()),
+            "/data/2.5/air_pollution/forecast",
+            &format!("lat={}&lon={}", lat, lon),
+        )
+        .await
     }
 
     pub async fn get_5day_3hour_forecast_by_name(
-        &mut self,
-        city_name: String,
-        country_code: Option<String>,
+        &self,
+        _city_name: String,
+        _country_code: Option<String>,
     ) -> Result<Forecast, Error> {
         todo!()
     }
 
     pub async fn get_lat_lon_by_name(
-        &mut self,
+        &self,
         city_name: String,
         country_code: Option<String>,
     ) -> Result<(Latitude, Longitude), Error> {
         const LIMIT: usize = 1;
 
-        let url = if let Some(country_code) = country_code {
-            format!(
-                "http://api.openweathermap.org/geo/1.0/direct?q={},{}&limit={}&appid={}",
-                city_name, country_code, LIMIT, self.api_key
-            )
+        let query = if let Some(country_code) = country_code {
+            format!("q={},{}&limit={}", city_name, country_code, LIMIT)
         } else {
-            format!(
-                "http://api.openweathermap.org/geo/1.0/direct?q={}&limit={}&appid={}",
-                city_name, LIMIT, self.api_key
-            )
+            format!("q={}&limit={}", city_name, LIMIT)
         };
 
-        let response = reqwest::get(url).await?;
-
-        Self::handle_status_code(&response)?;
+        let _response_text = self.http.get("/geo/1.0/direct", &query).await?;
 
         todo!();
     }
 
-    fn handle_status_code(response: &reqwest::Response) -> Result<(), Error> {
-        if 200 > response.status().as_u16() || response.status().as_u16() >= 300 {
-            return Err(Error::StatusCode(response.status()));
+    /// The remaining API calls.
+    pub fn budget_status(&self) -> BudgetStatus {
+        self.http.budget_status()
+    }
+
+    /// The center of the cache grid cell containing the location.
+    ///
+    /// All locations in a cell share their cached forecasts, so prefetching the center covers the whole cell.
+    pub fn cell_center(&self, lat: Latitude, lon: Longitude) -> (Latitude, Longitude) {
+        self.cache.cell_center(lat, lon)
+    }
+
+    /// The counters of all caches summed up.
+    pub fn cache_stats(&self) -> CacheStats {
+        [
+            self.cache.stats(),
+            self.air_pollution_cache.stats(),
+            self.one_call_cache.stats(),
+        ]
+        .into_iter()
+        .fold(CacheStats::default(), |sum, stats| CacheStats {
+            hits: sum.hits + stats.hits,
+            misses: sum.misses + stats.misses,
+            evictions: sum.evictions + stats.evictions,
+            stale_served: sum.stale_served + stats.stale_served,
+            len: sum.len + stats.len,
+            capacity: sum.capacity + stats.capacity,
+        })
+    }
+
+    /// Lookup `cache` or fetch a new forecast from `path` and cache it.
+    ///
+    /// Expired forecasts are served if the budget is low, OWM is unreachable or fetching fails, flagged with their age.
+    async fn cached_get<F, V>(
+        http: &HttpClient,
+        cache: &SharedCache<F, V>,
+        (lat, lon, variant): (Latitude, Longitude, V),
+        path: &str,
+        query: &str,
+    ) -> Result<MaybeStale<F>, Error>
+    where
+        F: Debug + Clone + Serialize + DeserializeOwned,
+        V: Debug + Clone + Eq + Hash + Serialize + DeserializeOwned,
+    {
+        if let Some(forecast_hit) = cache.lock().lookup(lat, lon, variant.clone()) {
+            return Ok(MaybeStale::fresh(forecast_hit));
+        };
+
+        // Concurrent lookups of the same entry share the result of one fetch instead of sending their own.
+        let flight = match cache.join_flight(lat, lon, variant.clone()).await {
+            Joined::Leader(flight) => flight,
+            Joined::Landed(result) => return result,
+        };
+
+        let result = Self::fetch_or_stale(http, cache, (lat, lon, variant), path, query).await;
+        flight.land(result.clone());
+
+        result
+    }
+
+    /// Fetch a new forecast from `path` and cache it or fall back to an expired one.
+    async fn fetch_or_stale<F, V>(
+        http: &HttpClient,
+        cache: &SharedCache<F, V>,
+        (lat, lon, variant): (Latitude, Longitude, V),
+        path: &str,
+        query: &str,
+    ) -> Result<MaybeStale<F>, Error>
+    where
+        F: Debug + Clone + Serialize + DeserializeOwned,
+        V: Debug + Clone + Eq + Hash + Serialize + DeserializeOwned,
+    {
+        if let Some(forecast_hit) = cache.lock().peek(lat, lon, variant.clone()) {
+            return Ok(MaybeStale::fresh(forecast_hit));
         };
 
-        Ok(())
+        let skip_reason = if http.budget_state() != BudgetState::Available {
+            Some("Request budget low")
+        } else if http.is_unreachable() {
+            Some("OWM unreachable")
+        } else {
+            None
+        };
+        if let Some(skip_reason) = skip_reason
+            && let Some(stale) = cache.lock().lookup_stale(lat, lon, variant.clone())
+        {
+            tracing::warn!(
+                "{}: Serving expired {} at Lat: {}, Lon: {}",
+                skip_reason,
+                path,
+                lat,
+                lon
+            );
+            return Ok(stale);
+        };
+
+        // With an expired forecast to fall back to, do not wait for the retries of an unreachable OWM.
+        let response = if cache.lock().has_stale(lat, lon, variant.clone()) {
+            http.get_or_fail_fast(path, query).await
+        } else {
+            http.get(path, query).await
+        };
+
+        let fetched = match response {
+            Ok(response_text) => {
+                tracing::debug!(
+                    "{} Response at Lat: {}, Lon: {}: {}",
+                    path,
+                    lat,
+                    lon,
+                    response_text
+                );
+
+                serde_json::from_str::<F>(&response_text).map_err(Error::from)
+            }
+            Err(e) => Err(e),
+        };
+
+        match fetched {
+            Ok(forecast) => {
+                cache.lock().cache(lat, lon, variant, forecast.clone());
+                Ok(MaybeStale::fresh(forecast))
+            }
+            Err(e) if !e.is_fatal() => match cache.lock().lookup_stale(lat, lon, variant) {
+                Some(stale) => {
+                    tracing::warn!(
+                        "Failed to fetch {}: {}: Serving expired forecast at Lat: {}, Lon: {}",
+                        path,
+                        e,
+                        lat,
+                        lon
+                    );
+                    Ok(stale)
+                }
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
     }
 }
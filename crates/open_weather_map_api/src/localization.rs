@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+
+/// The unit system and language of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Localization {
+    pub units: Units,
+    /// Language code for the weather descriptions. E.g. `en`, `de`, `zh_cn`.
+    pub lang: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Celsius and meter/sec.
+    #[default]
+    Metric,
+    /// Fahrenheit and miles/hour.
+    Imperial,
+    /// Kelvin and meter/sec.
+    Standard,
+}
+
+impl Localization {
+    pub fn new(units: Units, lang: String) -> Self {
+        Self { units, lang }
+    }
+
+    /// The query parameters for a request.
+    pub(crate) fn as_query(&self) -> String {
+        format!("units={}&lang={}", self.units.as_str(), self.lang)
+    }
+}
+
+impl Units {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Metric => "metric",
+            Self::Imperial => "imperial",
+            Self::Standard => "standard",
+        }
+    }
+
+    pub fn temperature_symbol(&self) -> &'static str {
+        match self {
+            Self::Metric => "°C",
+            Self::Imperial => "°F",
+            Self::Standard => "K",
+        }
+    }
+
+    pub fn speed_symbol(&self) -> &'static str {
+        match self {
+            Self::Metric | Self::Standard => "m/s",
+            Self::Imperial => "mph",
+        }
+    }
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self {
+            units: Units::Metric,
+            lang: String::from("de"),
+        }
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "metric" => Ok(Self::Metric),
+            "imperial" => Ok(Self::Imperial),
+            "standard" => Ok(Self::Standard),
+            _ => Err(crate::error::Error::InvalidUnits(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Latitude, Longitude, forecast::Weather};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OneCall {
+    pub lat: Latitude,
+    pub lon: Longitude,
+    /// Timezone name for the requested location.
+    pub timezone: String,
+    /// Shift in seconds from UTC.
+    pub timezone_offset: i32,
+    /// Current weather.
+    pub current: Option<Current>,
+    /// Minute forecast for 1 hour.
+    ///
+    /// Not available for every location.
+    #[serde(default)]
+    pub minutely: Vec<Minutely>,
+    /// Hour forecast for 48 hours.
+    #[serde(default)]
+    pub hourly: Vec<Hourly>,
+    /// Daily forecast for 8 days.
+    #[serde(default)]
+    pub daily: Vec<Daily>,
+    /// Government weather alerts relayed by OWM.
+    ///
+    /// Empty if there are no active alerts.
+    #[serde(default)]
+    pub alerts: Vec<Alert>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Current {
+    /// Current time, unix, UTC
+    pub dt: u64,
+    /// Sunrise time, unix, UTC. Missing in polar regions.
+    pub sunrise: Option<u64>,
+    /// Sunset time, unix, UTC. Missing in polar regions.
+    pub sunset: Option<u64>,
+    /// Temperature
+    pub temp: f32,
+    /// Feels like temperature. Takes the human perception into account.
+    pub feels_like: f32,
+    /// Atmospheric pressure at the sea level, hPa
+    pub pressure: f32,
+    /// Humidity, % 0 - 100
+    pub humidity: u8,
+    /// Dew point temperature.
+    pub dew_point: f32,
+    /// UV index.
+    pub uvi: f32,
+    /// Cloudiness, % 0 - 100
+    pub clouds: u8,
+    /// Average visibility in meters. Maximum is 10 km.
+    pub visibility: Option<u16>,
+    /// Wind speed.
+    pub wind_speed: f32,
+    /// Wind direction, degrees (meteorological)
+    pub wind_deg: u16,
+    /// Wind gust.
+    pub wind_gust: Option<f32>,
+    /// A list of Weather conditions.
+    pub weather: Vec<Weather>,
+    /// Rain volume in mm/h.
+    pub rain: Option<Precipitation1h>,
+    /// Snow volume in mm/h.
+    pub snow: Option<Precipitation1h>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Minutely {
+    /// Time of data forecasted, unix, UTC
+    pub dt: u64,
+    /// Precipitation in mm/h.
+    pub precipitation: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hourly {
+    /// Time of data forecasted, unix, UTC
+    pub dt: u64,
+    /// Temperature
+    pub temp: f32,
+    /// Feels like temperature. Takes the human perception into account.
+    pub feels_like: f32,
+    /// Atmospheric pressure at the sea level, hPa
+    pub pressure: f32,
+    /// Humidity, % 0 - 100
+    pub humidity: u8,
+    /// Dew point temperature.
+    pub dew_point: f32,
+    /// UV index.
+    pub uvi: f32,
+    /// Cloudiness, % 0 - 100
+    pub clouds: u8,
+    /// Average visibility in meters. Maximum is 10 km.
+    pub visibility: Option<u16>,
+    /// Wind speed.
+    pub wind_speed: f32,
+    /// Wind direction, degrees (meteorological)
+    pub wind_deg: u16,
+    /// Wind gust.
+    pub wind_gust: Option<f32>,
+    /// A list of Weather conditions.
+    pub weather: Vec<Weather>,
+    /// Probability of precipitation. %, 0 - 1
+    pub pop: f32,
+    /// Rain volume in mm/h.
+    pub rain: Option<Precipitation1h>,
+    /// Snow volume in mm/h.
+    pub snow: Option<Precipitation1h>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Daily {
+    /// Time of data forecasted, unix, UTC
+    pub dt: u64,
+    /// Sunrise time, unix, UTC. Missing in polar regions.
+    pub sunrise: Option<u64>,
+    /// Sunset time, unix, UTC. Missing in polar regions.
+    pub sunset: Option<u64>,
+    /// Human readable description of the day.
+    pub summary: Option<String>,
+    pub temp: DailyTemp,
+    pub feels_like: DailyFeelsLike,
+    /// Atmospheric pressure at the sea level, hPa
+    pub pressure: f32,
+    /// Humidity, % 0 - 100
+    pub humidity: u8,
+    /// Dew point temperature.
+    pub dew_point: f32,
+    /// Wind speed.
+    pub wind_speed: f32,
+    /// Wind direction, degrees (meteorological)
+    pub wind_deg: u16,
+    /// Wind gust.
+    pub wind_gust: Option<f32>,
+    /// A list of Weather conditions.
+    pub weather: Vec<Weather>,
+    /// Cloudiness, % 0 - 100
+    pub clouds: u8,
+    /// Probability of precipitation. %, 0 - 1
+    pub pop: f32,
+    /// Rain volume in mm.
+    pub rain: Option<f32>,
+    /// Snow volume in mm.
+    pub snow: Option<f32>,
+    /// Maximum UV index of the day.
+    pub uvi: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyTemp {
+    pub morn: f32,
+    pub day: f32,
+    pub eve: f32,
+    pub night: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyFeelsLike {
+    pub morn: f32,
+    pub day: f32,
+    pub eve: f32,
+    pub night: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Precipitation1h {
+    /// Precipitation volume for the last hour in mm.
+    #[serde(alias = "1h")]
+    pub one_hour: f32,
+}
+
+/// An official weather alert from a national warning system.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    /// Name of the alert source.
+    pub sender_name: String,
+    /// Alert event name.
+    pub event: String,
+    /// Start of the alert, unix, UTC
+    pub start: u64,
+    /// End of the alert, unix, UTC
+    pub end: u64,
+    /// Description of the alert.
+    pub description: String,
+    /// Type of severe weather.
+    #[serde(default)]
+    pub tags: Vec<AlertTag>,
+}
+
+/// The severe weather categories OWM tags alerts with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum AlertTag {
+    ExtremeTemperature,
+    Flood,
+    Wind,
+    Rain,
+    Thunderstorm,
+    SnowIce,
+    Fog,
+    CoastalEvent,
+    Avalanches,
+    Wildfires,
+    Tornado,
+    Tsunami,
+    AirQuality,
+    OtherDangers,
+    Other(String),
+}
+
+impl OneCall {
+    /// Active alerts at `timestamp`.
+    pub fn active_alerts(&self, timestamp: u64) -> impl Iterator<Item = &Alert> {
+        self.alerts
+            .iter()
+            .filter(move |alert| alert.start <= timestamp && timestamp < alert.end)
+    }
+}
+
+impl From<String> for AlertTag {
+    fn from(tag: String) -> Self {
+        match tag.as_str() {
+            "Extreme temperature value" => Self::ExtremeTemperature,
+            "Flood" => Self::Flood,
+            "Wind" => Self::Wind,
+            "Rain" => Self::Rain,
+            "Thunderstorm" => Self::Thunderstorm,
+            "Snow/Ice" => Self::SnowIce,
+            "Fog" => Self::Fog,
+            "Coastal event" => Self::CoastalEvent,
+            "Avalanches" => Self::Avalanches,
+            "Wildfires" => Self::Wildfires,
+            "Tornado" => Self::Tornado,
+            "Tsunami" => Self::Tsunami,
+            "Air quality" => Self::AirQuality,
+            "Other dangers" => Self::OtherDangers,
+            _ => Self::Other(tag),
+        }
+    }
+}
+
+impl From<AlertTag> for String {
+    fn from(tag: AlertTag) -> Self {
+        match tag {
+            AlertTag::ExtremeTemperature => "Extreme temperature value".to_string(),
+            AlertTag::Flood => "Flood".to_string(),
+            AlertTag::Wind => "Wind".to_string(),
+            AlertTag::Rain => "Rain".to_string(),
+            AlertTag::Thunderstorm => "Thunderstorm".to_string(),
+            AlertTag::SnowIce => "Snow/Ice".to_string(),
+            AlertTag::Fog => "Fog".to_string(),
+            AlertTag::CoastalEvent => "Coastal event".to_string(),
+            AlertTag::Avalanches => "Avalanches".to_string(),
+            AlertTag::Wildfires => "Wildfires".to_string(),
+            AlertTag::Tornado => "Tornado".to_string(),
+            AlertTag::Tsunami => "Tsunami".to_string(),
+            AlertTag::AirQuality => "Air quality".to_string(),
+            AlertTag::OtherDangers => "Other dangers".to_string(),
+            AlertTag::Other(tag) => tag,
+        }
+    }
+}
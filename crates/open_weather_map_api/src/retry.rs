@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// When and how often a failed request is repeated.
+///
+/// Timeouts, connection errors and 5xx responses are retried with jittered exponential backoff.
+/// 429 responses wait for `Retry-After` if sent, unless it is longer than `max_backoff_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Retries after the first attempt. 0 disables retrying.
+    pub max_retries: u32,
+    /// The backoff before the first retry in milliseconds. Doubles with every retry.
+    pub initial_backoff_ms: u64,
+    /// The upper limit of a single backoff in milliseconds.
+    ///
+    /// A longer `Retry-After` is not waited for, the request fails as rate limited instead.
+    pub max_backoff_ms: u64,
+}
+
+impl RetryConfig {
+    /// The backoff before retry number `retry` (starting at 0).
+    ///
+    /// Half of it is random to spread out the retries of concurrent requests.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff_ms
+            .saturating_mul(2u64.saturating_pow(retry))
+            .min(self.max_backoff_ms);
+        let half = backoff / 2;
+        let jitter = if half == 0 {
+            0
+        } else {
+            chrono::Utc::now().timestamp_subsec_nanos() as u64 % (half + 1)
+        };
+
+        Duration::from_millis(backoff - half + jitter)
+    }
+
+    /// The server requested `backoff` if it is within `max_backoff_ms`.
+    ///
+    /// `None` if the server asks to wait longer, retrying earlier would only be rejected again.
+    pub(crate) fn within_max(&self, backoff: Duration) -> Option<Duration> {
+        (backoff <= Duration::from_millis(self.max_backoff_ms)).then_some(backoff)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Tests of `OwmApi` against a local stub of the OWM API.
+
+use open_weather_map_api::{
+    CacheConfig, OwmApi, api_key::ApiKey, budget::BudgetConfig, http::HttpConfig,
+    localization::Localization, retry::RetryConfig,
+};
+
+use crate::stub_server::StubServer;
+
+mod recorded_responses;
+mod retry;
+mod single_flight;
+mod stale;
+mod stub_server;
+
+const API_KEY: &str = "test-key";
+const LAT: f64 = 52.52;
+const LON: f64 = 13.405;
+
+/// An `OwmApi` sending its requests to `server`, without persistence and with short backoffs.
+fn owm_api(server: &StubServer) -> OwmApi {
+    owm_api_with(server, HttpConfig::default(), cache_config())
+}
+
+fn owm_api_with(server: &StubServer, http_config: HttpConfig, cache_config: CacheConfig) -> OwmApi {
+    OwmApi::new(
+        ApiKey::new(API_KEY.to_string()),
+        &cache_config,
+        &HttpConfig {
+            base_url: server.base_url().to_string(),
+            retry: RetryConfig {
+                initial_backoff_ms: 10,
+                max_backoff_ms: 100,
+                ..http_config.retry.clone()
+            },
+            ..http_config
+        },
+        BudgetConfig::default(),
+    )
+    .unwrap()
+}
+
+fn cache_config() -> CacheConfig {
+    CacheConfig {
+        ttl: chrono::TimeDelta::hours(3),
+        grid_cell_km: 2.0,
+        soft_cache_limit: 32,
+        capacity: 256,
+        max_stale_age: chrono::TimeDelta::days(2),
+        persist_dir: None,
+    }
+}
+
+fn localization() -> Localization {
+    Localization::default()
+}
@@ -0,0 +1,105 @@
+//! Every endpoint parses a recorded OWM response and sends the expected request.
+
+use open_weather_map_api::air_pollution::Aqi;
+
+use crate::{
+    API_KEY, LAT, LON, localization, owm_api,
+    stub_server::{StubResponse, StubServer},
+};
+
+#[tokio::test]
+async fn forecast() {
+    let server = StubServer::start(vec![StubResponse::recorded("forecast")]).await;
+    let owm_api = owm_api(&server);
+
+    let forecast = owm_api
+        .get_5day_3hour_forecast(LAT, LON, Some(2), &localization())
+        .await
+        .unwrap();
+
+    assert!(!forecast.is_stale());
+    assert_eq!(forecast.list.len(), 2);
+    assert_eq!(forecast.city.name, "Berlin");
+    assert_eq!(forecast.list[0].weather[0].id, 500);
+    assert_eq!(
+        forecast.list[0].rain.as_ref().map(|rain| rain.three_hours),
+        Some(0.81)
+    );
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("/data/2.5/forecast?lat=52.52&lon=13.405&"));
+    assert!(requests[0].contains("&cnt=2"));
+    assert!(requests[0].contains(&format!("appid={}", API_KEY)));
+}
+
+#[tokio::test]
+async fn forecast_is_cached() {
+    let server = StubServer::start(vec![StubResponse::recorded("forecast")]).await;
+    let owm_api = owm_api(&server);
+
+    for _ in 0..3 {
+        owm_api
+            .get_5day_3hour_forecast(LAT, LON, Some(2), &localization())
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(owm_api.cache_stats().hits, 2);
+}
+
+#[tokio::test]
+async fn current_air_pollution() {
+    let server = StubServer::start(vec![StubResponse::recorded("air_pollution")]).await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await.unwrap();
+
+    assert_eq!(air_pollution.list.len(), 1);
+    assert_eq!(air_pollution.list[0].main.aqi, Aqi::Fair);
+    assert_eq!(air_pollution.list[0].components.pm2_5, 6.88);
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("/data/2.5/air_pollution?lat=52.52&lon=13.405&"));
+}
+
+#[tokio::test]
+async fn air_pollution_forecast() {
+    let server = StubServer::start(vec![StubResponse::recorded("air_pollution_forecast")]).await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_air_pollution_forecast(LAT, LON).await.unwrap();
+
+    assert_eq!(
+        air_pollution
+            .list
+            .iter()
+            .map(|segment| segment.main.aqi)
+            .max(),
+        Some(Aqi::Moderate)
+    );
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("/data/2.5/air_pollution/forecast?lat=52.52&lon=13.405&"));
+}
+
+#[tokio::test]
+async fn one_call() {
+    let server = StubServer::start(vec![StubResponse::recorded("one_call")]).await;
+    let owm_api = owm_api(&server);
+
+    let one_call = owm_api
+        .get_one_call(LAT, LON, &localization())
+        .await
+        .unwrap();
+
+    assert_eq!(one_call.minutely.len(), 2);
+    assert_eq!(one_call.daily[0].temp.max, 13.6);
+    assert_eq!(one_call.alerts.len(), 1);
+    assert_eq!(one_call.active_alerts(1760864400).count(), 1);
+    assert_eq!(one_call.active_alerts(1760896800).count(), 0);
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("/data/3.0/onecall?lat=52.52&lon=13.405&"));
+}
@@ -0,0 +1,93 @@
+//! Failed requests are retried or given up on depending on the failure.
+
+use open_weather_map_api::{error::Error, http::HttpConfig, retry::RetryConfig};
+
+use crate::{
+    LAT, LON, cache_config, owm_api, owm_api_with,
+    stub_server::{StubResponse, StubServer},
+};
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = StubServer::start(vec![
+        StubResponse::status(500),
+        StubResponse::status(503),
+        StubResponse::recorded("air_pollution"),
+    ])
+    .await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await;
+
+    assert!(air_pollution.is_ok());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() {
+    let server = StubServer::start(vec![StubResponse::status(502)]).await;
+    let owm_api = owm_api_with(
+        &server,
+        HttpConfig {
+            retry: RetryConfig {
+                max_retries: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        cache_config(),
+    );
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await;
+
+    assert!(matches!(air_pollution, Err(Error::StatusCode(status)) if status == 502));
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn waits_for_retry_after() {
+    let server = StubServer::start(vec![
+        StubResponse::status(429).header("Retry-After", "0"),
+        StubResponse::recorded("air_pollution"),
+    ])
+    .await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await;
+
+    assert!(air_pollution.is_ok());
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn fails_when_retry_after_exceeds_max_backoff() {
+    let server = StubServer::start(vec![
+        StubResponse::status(429).header("Retry-After", "3600"),
+        StubResponse::recorded("air_pollution"),
+    ])
+    .await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await;
+
+    assert!(matches!(
+        air_pollution,
+        Err(Error::RateLimited(Some(retry_after))) if retry_after.as_secs() == 3600
+    ));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn does_not_retry_invalid_api_key() {
+    let server = StubServer::start(vec![
+        StubResponse::status(401),
+        StubResponse::recorded("air_pollution"),
+    ])
+    .await;
+    let owm_api = owm_api(&server);
+
+    let air_pollution = owm_api.get_current_air_pollution(LAT, LON).await;
+
+    assert!(matches!(air_pollution, Err(Error::InvalidApiKey)));
+    assert_eq!(server.requests().len(), 1);
+}
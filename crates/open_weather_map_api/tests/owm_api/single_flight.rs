@@ -0,0 +1,69 @@
+//! Concurrent lookups of the same forecast share one fetch, whether it succeeds or fails.
+
+use std::time::Duration;
+
+use open_weather_map_api::{error::Error, http::HttpConfig, retry::RetryConfig};
+
+use crate::{
+    LAT, LON, cache_config, localization, owm_api, owm_api_with,
+    stub_server::{StubResponse, StubServer},
+};
+
+#[tokio::test]
+async fn shares_fetched_forecast() {
+    let server = StubServer::start(vec![
+        StubResponse::recorded("forecast").delay(Duration::from_millis(200)),
+    ])
+    .await;
+    let owm_api = owm_api(&server);
+    let localization = localization();
+    let get = || owm_api.get_5day_3hour_forecast(LAT, LON, None, &localization);
+
+    let forecasts = tokio::join!(get(), get(), get(), get(), get());
+
+    for forecast in [
+        forecasts.0,
+        forecasts.1,
+        forecasts.2,
+        forecasts.3,
+        forecasts.4,
+    ] {
+        assert_eq!(forecast.unwrap().city.name, "Berlin");
+    }
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn shares_failed_fetch() {
+    let server = StubServer::start(vec![
+        StubResponse::status(503).delay(Duration::from_millis(100)),
+    ])
+    .await;
+    let owm_api = owm_api_with(
+        &server,
+        HttpConfig {
+            retry: RetryConfig {
+                max_retries: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        cache_config(),
+    );
+    let localization = localization();
+    let get = || owm_api.get_5day_3hour_forecast(LAT, LON, None, &localization);
+
+    let forecasts = tokio::join!(get(), get(), get(), get(), get());
+
+    for forecast in [
+        forecasts.0,
+        forecasts.1,
+        forecasts.2,
+        forecasts.3,
+        forecasts.4,
+    ] {
+        assert!(matches!(forecast, Err(Error::StatusCode(status)) if status == 503));
+    }
+    // One retry chain for all of them.
+    assert_eq!(server.requests().len(), 3);
+}
@@ -0,0 +1,85 @@
+//! Expired forecasts are served when OWM can not be reached, without waiting for the retries.
+
+use std::time::{Duration, Instant};
+
+use open_weather_map_api::{CacheConfig, http::HttpConfig, retry::RetryConfig};
+
+use crate::{
+    LAT, LON, cache_config, localization, owm_api_with,
+    stub_server::{StubResponse, StubServer},
+};
+
+/// Forecasts expire at once, so every lookup tries to fetch a new one.
+fn expiring_cache_config() -> CacheConfig {
+    CacheConfig {
+        ttl: chrono::TimeDelta::zero(),
+        ..cache_config()
+    }
+}
+
+fn timeout_config() -> HttpConfig {
+    HttpConfig {
+        timeout_s: 1,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn serves_stale_at_once_when_owm_is_unreachable() {
+    let server = StubServer::start(vec![
+        StubResponse::recorded("forecast"),
+        StubResponse::unanswered(),
+    ])
+    .await;
+    let owm_api = owm_api_with(&server, timeout_config(), expiring_cache_config());
+
+    let fresh = owm_api
+        .get_5day_3hour_forecast(LAT, LON, None, &localization())
+        .await
+        .unwrap();
+    assert!(!fresh.is_stale());
+
+    // One timeout instead of the whole retry chain.
+    let started = Instant::now();
+    let stale = owm_api
+        .get_5day_3hour_forecast(LAT, LON, None, &localization())
+        .await
+        .unwrap();
+    assert!(stale.is_stale());
+    assert!(started.elapsed() < Duration::from_secs(3));
+    assert_eq!(server.requests().len(), 2);
+
+    // OWM counts as unreachable now, so it is not even asked.
+    let started = Instant::now();
+    let stale = owm_api
+        .get_5day_3hour_forecast(LAT, LON, None, &localization())
+        .await
+        .unwrap();
+    assert!(stale.is_stale());
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(server.requests().len(), 2);
+    assert_eq!(owm_api.cache_stats().stale_served, 2);
+}
+
+#[tokio::test]
+async fn retries_unreachable_owm_without_stale_forecast() {
+    let server = StubServer::start(vec![StubResponse::unanswered()]).await;
+    let owm_api = owm_api_with(
+        &server,
+        HttpConfig {
+            retry: RetryConfig {
+                max_retries: 1,
+                ..Default::default()
+            },
+            ..timeout_config()
+        },
+        expiring_cache_config(),
+    );
+
+    let forecast = owm_api
+        .get_5day_3hour_forecast(LAT, LON, None, &localization())
+        .await;
+
+    assert!(forecast.is_err_and(|e| e.is_unreachable()));
+    assert_eq!(server.requests().len(), 2);
+}
@@ -0,0 +1,186 @@
+//! A minimal HTTP server in place of the OWM API that answers with scripted responses.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+#[derive(Debug)]
+pub struct StubServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StubResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+    /// Keep the connection open without answering, like an uplink that drops all packets.
+    unanswered: bool,
+    delay: Duration,
+}
+
+impl StubServer {
+    /// Answer the requests with `responses` in order. The last response answers all further requests.
+    pub async fn start(responses: Vec<StubResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(VecDeque::from(responses)));
+
+        let task_requests = requests.clone();
+        let task = tokio::task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::task::spawn(Self::answer(
+                    stream,
+                    responses.clone(),
+                    task_requests.clone(),
+                ));
+            }
+        });
+
+        Self {
+            base_url,
+            requests,
+            task,
+        }
+    }
+
+    /// The URL to use as `HttpConfig::base_url`.
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The path and query of every request received so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn answer(
+        mut stream: TcpStream,
+        responses: Arc<Mutex<VecDeque<StubResponse>>>,
+        requests: Arc<Mutex<Vec<String>>>,
+    ) {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(len) => request.extend_from_slice(&buffer[..len]),
+            };
+        }
+
+        // E.g. `GET /data/2.5/forecast?lat=1&lon=2 HTTP/1.1`
+        let request = String::from_utf8_lossy(&request);
+        let target = request
+            .split_whitespace()
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+        requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(target);
+
+        let response = {
+            let mut responses = responses.lock().unwrap_or_else(PoisonError::into_inner);
+            if responses.len() > 1 {
+                responses.pop_front()
+            } else {
+                responses.front().cloned()
+            }
+        };
+        let Some(response) = response else {
+            return;
+        };
+        if response.unanswered {
+            return std::future::pending().await;
+        };
+
+        tokio::time::sleep(response.delay).await;
+
+        let _ = stream.write_all(&response.encode()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl StubResponse {
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+            unanswered: false,
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self {
+            body: body.into(),
+            ..Self::status(200)
+        }
+    }
+
+    /// A `200 OK` with the recorded OWM response `responses/<name>.json`.
+    pub fn recorded(name: &str) -> Self {
+        let path = format!(
+            "{}/tests/owm_api/responses/{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+
+        Self::ok(std::fs::read_to_string(&path).unwrap())
+    }
+
+    /// Never answer, the request times out.
+    pub fn unanswered() -> Self {
+        Self {
+            unanswered: true,
+            ..Self::status(200)
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Wait before answering, e.g. to let concurrent requests overlap.
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str("\r\n");
+        response.push_str(&self.body);
+
+        response.into_bytes()
+    }
+}
@@ -1,19 +1,69 @@
-use crate::{config::Config, consts::CONFIG_PATH};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
 
+use crate::{
+    config::Config,
+    consts::CONFIG_PATH,
+    essential_forecast,
+    preferences::{Preferences, Subscription},
+};
+
+pub mod command;
+pub mod delivery;
 pub mod error;
+pub mod prefetch;
+pub mod sent;
+/// The stub of the OWM API the OWM crate tests against.
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../crates/open_weather_map_api/tests/owm_api/stub_server.rs"]
+mod stub_server;
 
+use command::{Command, Coordinates, ParseError};
+use delivery::{DeliveryStats, Outgoing, OutgoingMessage};
 use error::Error;
-use meshtastic_api::MeshtasticApi;
-use open_weather_map_api::OwmApi;
+use meshtastic_api::{
+    MAX_PAYLOAD_SIZE, MeshtasticApi,
+    capture::Capture,
+    channel::Channel,
+    delivery::{Delivery, PendingDelivery},
+    packet::{Packet, Payload, Target},
+    scheduler::{Priority, Scheduled, Scheduler},
+    transport::Transport,
+};
+use open_weather_map_api::{
+    CacheConfig, MaybeStale, OwmApi,
+    air_pollution::Aqi,
+    api_key::ApiKey,
+    localization::{Localization, Units},
+    one_call::{Alert, OneCall},
+};
+use prefetch::{Prefetch, PrefetchPlanner};
+use sent::{SentMessage, SentMessages};
 
 #[derive(Debug)]
 pub struct Bot {
     config: Config,
+    preferences: Preferences,
     owm_api: OwmApi,
     meshtastic_api: MeshtasticApi,
     packet_receiver: tokio::sync::mpsc::Receiver<meshtastic_api::packet::Packet>,
-
-    listener_task: Option<tokio::task::JoinHandle<()>>,
+    /// The AQI level of the last air quality alert. `None` if the air quality is below the threshold.
+    air_quality_alerted: Option<Aqi>,
+    /// The already broadcasted weather alerts by event and start time.
+    weather_alerts_broadcasted: HashSet<(String, u64)>,
+    prefetch_planner: PrefetchPlanner,
+    /// The last delivery time of the subscription of each node.
+    subscriptions_delivered: HashMap<u32, chrono::DateTime<chrono::Local>>,
+    /// Receives the delivery outcomes of sent messages.
+    delivery_receiver: tokio::sync::mpsc::UnboundedReceiver<(OutgoingMessage, Delivery)>,
+    delivery_sender: tokio::sync::mpsc::UnboundedSender<(OutgoingMessage, Delivery)>,
+    delivery_stats: HashMap<u32, DeliveryStats>,
+    /// All sends wait here for their turn by priority and airtime budget, and while the radio is disconnected.
+    scheduler: Scheduler<Outgoing>,
+    sent: SentMessages,
 }
 
 impl Bot {
@@ -22,53 +72,1215 @@ impl Bot {
             Ok(config) => config,
             Err(config) => config,
         };
+        let transport = Self::transport(&config)?;
+
+        Self::with_transport(config, transport).await
+    }
+
+    /// Start the bot with `config`, connected to the radio over `transport`.
+    pub async fn with_transport(config: Config, transport: Transport) -> Result<Self, Error> {
+        let preferences = Preferences::load(&config.node_preferences_path).await?;
+
+        let owm_api_key = Self::load_owm_api_key(&config).await?;
+
+        let owm_api = OwmApi::new(
+            owm_api_key,
+            &CacheConfig {
+                ttl: chrono::TimeDelta::seconds(config.forecast.cache_ttl_s as i64),
+                grid_cell_km: config.forecast.cache_grid_km,
+                soft_cache_limit: config.forecast.soft_cache_limit,
+                capacity: config.forecast.cache_capacity,
+                max_stale_age: chrono::TimeDelta::seconds(config.forecast.max_stale_age_s as i64),
+                persist_dir: config.forecast.cache_dir.as_ref().map(PathBuf::from),
+            },
+            &config.owm_http,
+            config.owm_budget.clone(),
+        )?;
 
-        let owm_api_key = match std::env::var(&config.owm_api_key_env_var) {
-            Ok(key) => key,
+        let (packet_sender, packet_receiver) =
+            tokio::sync::mpsc::channel(config.meshtastic.packet_buffer);
+        let capture = match &config.meshtastic.capture_path {
+            Some(path) => Some(Capture::create(path).await?),
+            None => None,
+        };
+        let meshtastic_api =
+            meshtastic_api::MeshtasticApi::new(transport, packet_sender, capture).await?;
+
+        let prefetch_planner = PrefetchPlanner::new(
+            chrono::TimeDelta::seconds(config.subscriptions.prefetch_lead_s as i64),
+            config.subscriptions.max_prefetches_per_check,
+        );
+
+        let (delivery_sender, delivery_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let scheduler = Scheduler::new(
+            config.meshtastic.duty_cycle_percent / 100.0,
+            std::time::Duration::from_secs(config.meshtastic.duty_cycle_window_s as u64),
+            config.meshtastic.outbox_capacity,
+        );
+
+        Ok(Self {
+            config,
+            preferences,
+            owm_api,
+            meshtastic_api,
+            packet_receiver,
+            air_quality_alerted: None,
+            weather_alerts_broadcasted: HashSet::new(),
+            prefetch_planner,
+            subscriptions_delivered: HashMap::new(),
+            delivery_receiver,
+            delivery_sender,
+            delivery_stats: HashMap::new(),
+            scheduler,
+            sent: SentMessages::default(),
+        })
+    }
+
+    /// Read the OWM API key from `owm_api_key_file` if set, else from the `owm_api_key_env_var` environment variable.
+    async fn load_owm_api_key(config: &Config) -> Result<ApiKey, Error> {
+        if let Some(key_file) = &config.owm_api_key_file {
+            let mut path = std::path::PathBuf::from(key_file);
+            if path.is_relative()
+                && let Some(credentials_directory) = std::env::var_os("CREDENTIALS_DIRECTORY")
+            {
+                path = std::path::PathBuf::from(credentials_directory).join(path);
+            };
+
+            let key = tokio::fs::read_to_string(&path).await.map_err(|e| {
+                tracing::error!(
+                    "Failed to read the Open Weather API Key from {}!",
+                    path.to_string_lossy()
+                );
+                Error::OpenWeatherMapApiKeyFile(e)
+            })?;
+
+            return Ok(ApiKey::new(key.trim().to_string()));
+        };
+
+        match std::env::var(&config.owm_api_key_env_var) {
+            Ok(key) => Ok(ApiKey::new(key)),
             Err(std::env::VarError::NotPresent) => {
                 tracing::error!(
                     "Failed to fetch the Open Weather API Key from enviroment variables!"
                 );
 
-                return Err(Error::OpenWeatherMapApiKeyPath(
+                Err(Error::OpenWeatherMapApiKeyPath(
                     std::env::VarError::NotPresent,
-                ));
+                ))
+            }
+            Err(e) => Err(Error::OpenWeatherMapApiKeyPath(e)),
+        }
+    }
+
+    /// The transport to the radio selected by the `[meshtastic]` config.
+    fn transport(config: &Config) -> Result<Transport, Error> {
+        let transport = match (
+            &config.meshtastic.replay_path,
+            &config.meshtastic.tcp_address,
+            config.meshtastic.usb_vid,
+            config.meshtastic.usb_pid,
+        ) {
+            (Some(path), _, _, _) => Transport::Replay {
+                path: path.clone(),
+                speed: config.meshtastic.replay_speed,
+            },
+            (None, Some(address), _, _) => Transport::tcp(address),
+            (None, None, Some(vid), Some(pid)) => Transport::Usb { vid, pid },
+            _ => {
+                let available_ports = meshtastic::utils::stream::available_serial_ports()?;
+                tracing::info!("Available Serial Ports: {:?}", available_ports);
+
+                if config.meshtastic.serial_path == Config::default().meshtastic.serial_path {
+                    tracing::error!("Please set the Meshtastic serial path!");
+                };
+
+                Transport::Serial(config.meshtastic.serial_path.clone())
             }
-            Err(e) => return Err(Error::OpenWeatherMapApiKeyPath(e)),
         };
 
-        let owm_api = OwmApi::new(
-            owm_api_key,
-            chrono::TimeDelta::seconds(config.forecast.cache_ttl_s as i64),
-            config.forecast.soft_cache_limit,
+        Ok(transport)
+    }
+
+    pub async fn run(&mut self) -> Result<(), Error> {
+        let mut air_quality_interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.air_quality.alert_check_interval_s as u64,
+        ));
+        let mut weather_alerts_interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.weather_alerts.check_interval_s as u64,
+        ));
+        let mut subscriptions_interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.subscriptions.check_interval_s as u64,
+        ));
+        let mut telemetry_interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.telemetry.interval_s as u64,
+        ));
+
+        let initial_reconnect_backoff = std::time::Duration::from_secs(
+            self.config.meshtastic.reconnect_initial_backoff_s as u64,
         );
+        let max_reconnect_backoff =
+            std::time::Duration::from_secs(self.config.meshtastic.reconnect_max_backoff_s as u64);
+        let mut reconnect_backoff = initial_reconnect_backoff;
+        let reconnect_sleep = tokio::time::sleep(std::time::Duration::ZERO);
+        tokio::pin!(reconnect_sleep);
+
+        loop {
+            tokio::select! {
+                packet = self.packet_receiver.recv() => {
+                    let Some(packet) = packet else {
+                        tracing::error!("Meshtastic packet channel closed.");
+                        break;
+                    };
+
+                    self.handle_packet(packet).await?;
+                }
+                Some((message, delivery)) = self.delivery_receiver.recv() => {
+                    self.handle_delivery(message, delivery);
+                }
+                scheduled = self.scheduler.next(), if self.meshtastic_api.is_connected() => {
+                    self.transmit(scheduled).await;
+                }
+                _ = self.meshtastic_api.disconnected(), if self.meshtastic_api.is_connected() => {
+                    tracing::warn!("Lost the connection to the radio. Reconnecting...");
+                    reconnect_backoff = initial_reconnect_backoff;
+                    reconnect_sleep.as_mut().reset(tokio::time::Instant::now());
+                }
+                _ = &mut reconnect_sleep, if !self.meshtastic_api.is_connected() => {
+                    match self.meshtastic_api.reconnect().await {
+                        Ok(()) => {
+                            tracing::info!(
+                                "Reconnected to the radio. {} sends queued.",
+                                self.scheduler.depth()
+                            );
+                        }
+                        Err(e) => {
+                            tracing::error!(
+                                "Failed to reconnect to the radio: {}: Retry in {}s",
+                                e,
+                                reconnect_backoff.as_secs()
+                            );
+                            reconnect_sleep
+                                .as_mut()
+                                .reset(tokio::time::Instant::now() + reconnect_backoff);
+                            reconnect_backoff = (reconnect_backoff * 2).min(max_reconnect_backoff);
+                        }
+                    };
+                }
+                _ = air_quality_interval.tick(), if self.config.air_quality.alert_threshold.is_some() => {
+                    if let Err(e) = self.check_air_quality_alert().await {
+                        tracing::error!("Failed to check the air quality: {}", e);
+                        if e.is_fatal() {
+                            return Err(e);
+                        };
+                    };
+                }
+                _ = weather_alerts_interval.tick(), if self.config.weather_alerts.broadcast => {
+                    if let Err(e) = self.broadcast_weather_alerts().await {
+                        tracing::error!("Failed to check the weather alerts: {}", e);
+                        if e.is_fatal() {
+                            return Err(e);
+                        };
+                    };
+                }
+                _ = telemetry_interval.tick(), if self.config.telemetry.broadcast => {
+                    if let Err(e) = self.broadcast_telemetry().await {
+                        tracing::error!("Failed to broadcast the telemetry: {}", e);
+                        if e.is_fatal() {
+                            return Err(e);
+                        };
+                    };
+                }
+                _ = subscriptions_interval.tick() => {
+                    self.deliver_subscriptions().await?;
+                    self.prefetch_subscriptions().await?;
+                }
+            }
+        }
 
-        let available_ports = meshtastic::utils::stream::available_serial_ports()?;
-        tracing::info!("Available Serial Ports: {:?}", available_ports);
+        Ok(())
+    }
 
-        if config.meshtastic.serial_path == Config::default().meshtastic.serial_path {
-            tracing::error!("Please set the Meshtastic serial path!");
+    /// Answer a command.
+    ///
+    /// Only fatal errors are returned, all others are logged and answered with an apology.
+    async fn handle_packet(&mut self, packet: Packet) -> Result<(), Error> {
+        match &packet.payload {
+            Payload::Reaction(emoji) => {
+                self.handle_reaction(&packet, emoji);
+                return Ok(());
+            }
+            Payload::Reply(text) if text.trim().eq_ignore_ascii_case("more") => {
+                if let Some(rest) = self.sent.take_continuation(packet.reply_id) {
+                    self.reply(&packet, rest);
+                };
+                return Ok(());
+            }
+            _ => {}
         };
 
-        let (packet_sender, packet_receiver) =
-            tokio::sync::mpsc::channel(config.meshtastic.packet_buffer);
-        let meshtastic_api = meshtastic_api::MeshtasticApi::new(
-            config.meshtastic.serial_path.clone(),
-            packet_sender,
+        // Other replies are read as commands too.
+        let Some(text) = packet.text() else {
+            return Ok(());
+        };
+
+        let command = match Command::parse(text) {
+            Ok(command) => command,
+            Err(ParseError::Unknown) => return Ok(()),
+            // Channel chat often starts with a keyword too, e.g. "rain is coming tonight".
+            Err(_) if !packet.is_direct_to(*self.meshtastic_api.get_node_id()) => return Ok(()),
+            Err(e) => {
+                self.reply(&packet, e.to_string());
+                return Ok(());
+            }
+        };
+
+        tracing::info!(
+            "Command from {} (SNR {} dB, RSSI {} dBm): {:?}",
+            packet.from,
+            packet.rx_snr,
+            packet.rx_rssi,
+            command
+        );
+
+        if let Some(emoji) = self.config.meshtastic.receipt_emoji.clone() {
+            self.react(&packet, emoji);
+        };
+
+        let localization = self
+            .preferences
+            .localization(packet.from, &self.config.forecast.localization);
+        let reply = match command {
+            Command::Forecast(coords) => self.forecast_reply(coords, &localization).await,
+            Command::Air(coords) => self.air_quality_reply(coords).await,
+            Command::Rain(coords) => self.rain_reply(coords, &localization).await,
+            Command::Alerts(coords) => self.weather_alerts_reply(coords, &localization).await,
+            Command::Status => Ok(self.status_reply()),
+            Command::Units(units) => {
+                self.preferences.node_mut(packet.from).units = Some(units);
+                self.write_preferences()
+                    .await
+                    .map(|_| format!("Units set to {}.", units.as_str()))
+            }
+            Command::Lang(lang) => {
+                let reply = format!("Language set to {}.", lang);
+                self.preferences.node_mut(packet.from).lang = Some(lang);
+                self.write_preferences().await.map(|_| reply)
+            }
+            Command::Subscribe(subscription) => {
+                let reply = format!(
+                    "Daily forecast at {:02}:{:02}.",
+                    subscription.hour, subscription.minute
+                );
+                self.preferences.node_mut(packet.from).subscription = Some(subscription);
+                self.write_preferences().await.map(|_| reply)
+            }
+            Command::Unsubscribe => {
+                self.preferences.node_mut(packet.from).subscription = None;
+                self.write_preferences()
+                    .await
+                    .map(|_| String::from("Daily forecast stopped."))
+            }
+        };
+
+        match reply {
+            Ok(reply) => self.reply(&packet, reply),
+            Err(e) => {
+                tracing::error!("Failed to answer command: {}", e);
+                self.reply(&packet, String::from("Sorry, the request failed."));
+
+                if e.is_fatal() {
+                    return Err(e);
+                };
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Answer to a packet. Direct messages get a direct message, channel messages get answered in the channel.
+    ///
+    /// The answer is threaded to the packet, in channels it is addressed to the short name of the requester.
+    /// Too long answers are split, the rest is sent when the node replies `more`.
+    fn reply(&mut self, packet: &Packet, text: String) {
+        let (target, channel) = Self::reply_target(packet);
+        let text = match (&target, self.meshtastic_api.nodes().short_name(packet.from)) {
+            (Target::PrimaryChannel, Some(short_name)) => format!("@{} {}", short_name, text),
+            _ => text,
+        };
+
+        self.send_continued(
+            OutgoingMessage::new(text, target, channel, Priority::Reply).replying_to(packet.id),
+        );
+    }
+
+    /// React to a packet with `emoji` as a receipt.
+    fn react(&mut self, packet: &Packet, emoji: String) {
+        let (target, channel) = Self::reply_target(packet);
+
+        self.enqueue(Outgoing::Reaction {
+            emoji,
+            reply_id: packet.id,
+            target,
+            channel,
+        });
+    }
+
+    fn reply_target(packet: &Packet) -> (Target, Option<Channel>) {
+        match packet.to {
+            Target::PrimaryChannel => (Target::PrimaryChannel, Some(Channel::from(packet.channel))),
+            Target::NodeId(_) => (Target::NodeId(packet.from), None),
+        }
+    }
+
+    /// Record the acknowledgement of an alert. Other reactions are only logged.
+    fn handle_reaction(&mut self, packet: &Packet, emoji: &str) {
+        let sent = packet
+            .replies_to()
+            .and_then(|reply_id| self.sent.get_mut(reply_id));
+
+        match sent {
+            Some(SentMessage::Alert {
+                text,
+                acknowledged_by,
+            }) => {
+                acknowledged_by.insert(packet.from);
+                tracing::info!(
+                    "{} acknowledged the alert \"{}\" with {} ({} acknowledgements)",
+                    packet.from,
+                    text,
+                    emoji,
+                    acknowledged_by.len()
+                );
+            }
+            _ => tracing::debug!(
+                "Reaction {} from {} to {:?}",
+                emoji,
+                packet.from,
+                packet.replies_to()
+            ),
+        };
+    }
+
+    /// Send the part of a message that fits into one packet, the rest is sent when the target replies `more`.
+    fn send_continued(&mut self, mut message: OutgoingMessage) {
+        let (text, rest) = Self::split_payload(message.text);
+        message.text = text;
+        message.on_sent = rest.map(SentMessage::Continued);
+
+        self.enqueue(Outgoing::Message(message));
+    }
+
+    /// Queue a send. It is sent by `transmit` when its priority and the airtime budget allow.
+    fn enqueue(&mut self, outgoing: Outgoing) {
+        let priority = outgoing.priority();
+        let airtime = self.meshtastic_api.airtime(outgoing.payload_len());
+
+        if let Some(dropped) = self.scheduler.push(priority, airtime, outgoing) {
+            tracing::warn!("Outbound queue full, dropping: {:?}", dropped);
+        };
+    }
+
+    /// Send a queued send. Messages report their delivery outcome to `handle_delivery` in the background.
+    ///
+    /// Sends that failed because the radio disconnected are queued again.
+    async fn transmit(&mut self, mut scheduled: Scheduled<Outgoing>) {
+        let sent = match &scheduled.item {
+            Outgoing::Message(message) => self
+                .meshtastic_api
+                .send_message_tracked(
+                    message.text.clone(),
+                    message.target.clone(),
+                    message.channel,
+                    message.reply_id,
+                )
+                .await
+                .map(Some),
+            Outgoing::Reaction {
+                emoji,
+                reply_id,
+                target,
+                channel,
+            } => self
+                .meshtastic_api
+                .send_reaction(emoji, *reply_id, target.clone(), *channel)
+                .await
+                .map(|_| None),
+            Outgoing::Telemetry(metrics) => self
+                .meshtastic_api
+                .send_environment_metrics(*metrics, None)
+                .await
+                .map(|_| None),
+        };
+
+        match sent {
+            Ok(pending) => {
+                self.scheduler.spend(scheduled.airtime);
+                if let (Some(pending), Outgoing::Message(message)) = (pending, scheduled.item) {
+                    self.track_delivery(message, pending);
+                };
+            }
+            Err(meshtastic_api::error::SendError::Disconnected) => {
+                tracing::debug!("Radio disconnected, queued again: {:?}", scheduled.item);
+                scheduled.airtime = self.meshtastic_api.airtime(scheduled.item.payload_len());
+                self.scheduler.requeue(scheduled);
+            }
+            Err(e) => tracing::error!("Failed to send {:?}: {}", scheduled.item, e),
+        };
+    }
+
+    /// Remember what the message is by its packet id and wait for its delivery outcome in the background.
+    fn track_delivery(&mut self, message: OutgoingMessage, pending: PendingDelivery) {
+        tracing::debug!("Sent packet {} to {:?}", pending.id, message.target);
+        // Kept in the message too, a retry is a new packet to remember.
+        if let Some(on_sent) = &message.on_sent {
+            self.sent.record(pending.id, on_sent.clone());
+        };
+
+        let timeout =
+            std::time::Duration::from_secs(self.config.meshtastic.delivery_timeout_s as u64);
+        let delivery_sender = self.delivery_sender.clone();
+        tokio::task::spawn(async move {
+            let delivery = pending.outcome(timeout).await;
+            let _ = delivery_sender.send((message, delivery));
+        });
+    }
+
+    /// Record the delivery outcome of a message and send it again if it was not delivered.
+    fn handle_delivery(&mut self, mut message: OutgoingMessage, delivery: Delivery) {
+        if let Target::NodeId(node) = message.target {
+            let stats = self.delivery_stats.entry(node).or_default();
+            stats.record(delivery.is_delivered());
+            tracing::info!(
+                "Delivery to {}: {}. {} of {} messages delivered ({:.0}%).",
+                node,
+                delivery,
+                stats.delivered,
+                stats.delivered + stats.failed,
+                stats.rate() * 100.0
+            );
+        } else {
+            tracing::debug!("Delivery to {:?}: {}", message.target, delivery);
+        };
+
+        if delivery.is_delivered() || message.retry >= self.config.meshtastic.max_delivery_retries {
+            return;
+        };
+
+        message.retry += 1;
+        tracing::warn!(
+            "Sending the message to {:?} again: Retry {} of {}",
+            message.target,
+            message.retry,
+            self.config.meshtastic.max_delivery_retries
+        );
+        self.enqueue(Outgoing::Message(message));
+    }
+
+    /// Send the daily forecast to every node whose delivery time has come.
+    ///
+    /// Deliveries missed by more than `DELIVERY_WINDOW_MIN`, e.g. while the bot was offline, are skipped.
+    /// Only fatal errors are returned, all others are logged.
+    async fn deliver_subscriptions(&mut self) -> Result<(), Error> {
+        const DELIVERY_WINDOW_MIN: i64 = 15;
+
+        let now = chrono::Local::now();
+        let due: Vec<(u32, Subscription, chrono::DateTime<chrono::Local>)> = self
+            .preferences
+            .subscriptions()
+            .filter_map(|(node, subscription)| {
+                let delivery = subscription.previous_delivery(now)?;
+                let is_due = now - delivery < chrono::TimeDelta::minutes(DELIVERY_WINDOW_MIN)
+                    && self.subscriptions_delivered.get(&node) != Some(&delivery);
+
+                is_due.then_some((node, *subscription, delivery))
+            })
+            .collect();
+
+        for (node, subscription, delivery) in due {
+            let _ = self.subscriptions_delivered.insert(node, delivery);
+
+            let localization = self
+                .preferences
+                .localization(node, &self.config.forecast.localization);
+            let text = match self
+                .forecast_reply(subscription.coords, &localization)
+                .await
+            {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::error!("Failed to deliver the subscription of {}: {}", node, e);
+                    if e.is_fatal() {
+                        return Err(e);
+                    };
+                    continue;
+                }
+            };
+
+            self.send_continued(OutgoingMessage::new(
+                text,
+                Target::NodeId(node),
+                None,
+                Priority::Subscription,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch the forecasts of subscriptions delivered soon into the cache.
+    ///
+    /// Half of the calls left this minute are kept for requests of the nodes.
+    /// Only fatal errors are returned, all others are logged.
+    async fn prefetch_subscriptions(&mut self) -> Result<(), Error> {
+        let now = chrono::Local::now();
+        let upcoming: Vec<Prefetch> = self
+            .preferences
+            .subscriptions()
+            .filter_map(|(node, subscription)| {
+                let (lat, lon) = self.coords_or_home(subscription.coords);
+
+                Some(Prefetch::new(
+                    subscription.next_delivery(now)?,
+                    self.owm_api.cell_center(lat, lon),
+                    self.preferences
+                        .localization(node, &self.config.forecast.localization),
+                ))
+            })
+            .collect();
+
+        let budget = self.owm_api.budget_status().remaining_minute as usize / 2;
+        for prefetch in self.prefetch_planner.plan(now, upcoming, budget) {
+            let (lat, lon) = prefetch.coords();
+            tracing::debug!(
+                "Prefetching the forecast at Lat: {}, Lon: {} for {}",
+                lat,
+                lon,
+                prefetch.delivery
+            );
+
+            if let Err(e) = self
+                .owm_api
+                .get_5day_3hour_forecast(
+                    lat,
+                    lon,
+                    Some(self.config.forecast.forecast_count),
+                    &prefetch.localization,
+                )
+                .await
+            {
+                tracing::warn!(
+                    "Failed to prefetch the forecast at Lat: {}, Lon: {}: {}",
+                    lat,
+                    lon,
+                    e
+                );
+                if e.is_fatal() {
+                    return Err(e.into());
+                };
+            };
+        }
+
+        Ok(())
+    }
+
+    async fn write_preferences(&self) -> Result<(), Error> {
+        Ok(self
+            .preferences
+            .write(&self.config.node_preferences_path)
+            .await?)
+    }
+
+    fn status_reply(&mut self) -> String {
+        let budget = self.owm_api.budget_status();
+        let cache = self.owm_api.cache_stats();
+
+        format!(
+            "OWM calls left: {}/{} today, {}/{} this minute\nCache: {}/{} entries, {} hits, {} misses, {} evicted, {} stale\nQueue: {} waiting, {:.0}/{:.0}s airtime",
+            budget.remaining_day,
+            budget.calls_per_day,
+            budget.remaining_minute,
+            budget.calls_per_minute,
+            cache.len,
+            cache.capacity,
+            cache.hits,
+            cache.misses,
+            cache.evictions,
+            cache.stale_served,
+            self.scheduler.depth(),
+            self.scheduler.airtime_spent().as_secs_f32(),
+            self.scheduler.airtime_budget().as_secs_f32()
         )
-        .await?;
+    }
 
-        Ok(Self {
-            config,
-            owm_api,
-            meshtastic_api,
-            packet_receiver,
+    async fn forecast_reply(
+        &mut self,
+        coords: Option<Coordinates>,
+        localization: &Localization,
+    ) -> Result<String, Error> {
+        let (lat, lon) = self.coords_or_home(coords);
+        let forecast = self
+            .owm_api
+            .get_5day_3hour_forecast(
+                lat,
+                lon,
+                Some(self.config.forecast.forecast_count),
+                localization,
+            )
+            .await?;
+
+        let offset = chrono::FixedOffset::east_opt(forecast.city.timezone as i32)
+            .unwrap_or(chrono::FixedOffset::east_opt(0).expect("UTC is a valid offset"));
+        let temperature_symbol = localization.units.temperature_symbol();
+        let stale_note = Self::stale_note(forecast.stale_age);
+
+        let essential_forecast::Forecast::Hour3(segments) =
+            essential_forecast::Forecast::try_from(forecast.forecast)?;
+        let lines: Vec<String> = segments
+            .iter()
+            .map(|segment| {
+                let time = chrono::DateTime::from_timestamp(segment.date_time as i64, 0)
+                    .map(|time| time.with_timezone(&offset).format("%Hh").to_string())
+                    .unwrap_or_default();
+                let description = segment
+                    .weather
+                    .first()
+                    .map(|weather| weather.as_str())
+                    .unwrap_or_default();
+
+                let mut line = format!(
+                    "{} {:.0}{} {}",
+                    time, segment.temp.temp, temperature_symbol, description
+                );
+                if segment.pop >= 0.1 {
+                    line.push_str(&format!(" {:.0}%", segment.pop * 100.0));
+                };
+
+                line
+            })
+            .collect();
+
+        // Lead with the note, long forecasts are split and the rest is only sent on `more`.
+        match stale_note.trim_start() {
+            "" => Ok(lines.join("\n")),
+            note => Ok(format!("{}\n{}", note, lines.join("\n"))),
+        }
+    }
+
+    async fn air_quality_reply(&mut self, coords: Option<Coordinates>) -> Result<String, Error> {
+        let (lat, lon) = self.coords_or_home(coords);
+
+        let current = self.owm_api.get_current_air_pollution(lat, lon).await?;
+        let Some(current) = current.list.first() else {
+            return Ok(String::from("No air quality data available."));
+        };
+        let components = &current.components;
+
+        let mut reply = format!(
+            "Air: {} PM2.5 {:.0} PM10 {:.0} O3 {:.0} NO2 {:.0} SO2 {:.0} CO {:.0} µg/m³",
+            current.main.aqi,
+            components.pm2_5,
+            components.pm10,
+            components.o3,
+            components.no2,
+            components.so2,
+            components.co,
+        );
+
+        let max_aqi = self.max_aqi_next_24h(lat, lon).await?;
+        if let Some(aqi) = max_aqi.forecast {
+            reply.push_str(&format!(", 24h max: {}", aqi));
+            reply.push_str(&Self::stale_note(max_aqi.stale_age));
+        };
+
+        Ok(reply)
+    }
+
+    /// Broadcast an alert if the air quality is forecast to reach the configured threshold.
+    ///
+    /// Alerts again only if the level gets worse or after the air quality was below the threshold.
+    async fn check_air_quality_alert(&mut self) -> Result<(), Error> {
+        let Some(threshold) = self.config.air_quality.alert_threshold else {
+            return Ok(());
+        };
+        let (lat, lon) = self.coords_or_home(None);
+
+        let max_aqi = self.max_aqi_next_24h(lat, lon).await?;
+        match max_aqi.forecast {
+            Some(aqi) if aqi >= threshold => {
+                if self
+                    .air_quality_alerted
+                    .is_some_and(|alerted| alerted >= aqi)
+                {
+                    return Ok(());
+                };
+
+                tracing::info!("Air quality alert: {}", aqi);
+                let text = format!(
+                    "Air quality alert: AQI forecast to reach {} within 24h. Limit time outdoors.",
+                    aqi
+                );
+                let mut message = OutgoingMessage::new(
+                    text.clone(),
+                    Target::PrimaryChannel,
+                    None,
+                    Priority::Alert,
+                );
+                message.on_sent = Some(SentMessage::alert(text));
+                self.enqueue(Outgoing::Message(message));
+                self.air_quality_alerted = Some(aqi);
+            }
+            _ => self.air_quality_alerted = None,
+        };
 
-            listener_task: None,
+        Ok(())
+    }
+
+    /// The worst forecasted AQI within the next 24 hours.
+    async fn max_aqi_next_24h(
+        &mut self,
+        lat: f64,
+        lon: f64,
+    ) -> Result<MaybeStale<Option<Aqi>>, Error> {
+        let forecast = self.owm_api.get_air_pollution_forecast(lat, lon).await?;
+        let now = chrono::Utc::now();
+        let (from, until) = (
+            (now - chrono::TimeDelta::hours(1)).timestamp() as u64,
+            (now + chrono::TimeDelta::hours(24)).timestamp() as u64,
+        );
+
+        Ok(MaybeStale {
+            forecast: forecast
+                .list
+                .iter()
+                .filter(|segment| from <= segment.dt && segment.dt <= until)
+                .map(|segment| segment.main.aqi)
+                .max(),
+            stale_age: forecast.stale_age,
         })
     }
 
-    pub async fn run(&mut self) -> Result<(), Error> {
+    async fn rain_reply(
+        &mut self,
+        coords: Option<Coordinates>,
+        localization: &Localization,
+    ) -> Result<String, Error> {
+        let (lat, lon) = self.coords_or_home(coords);
+        let one_call = self.owm_api.get_one_call(lat, lon, localization).await?;
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        if one_call.is_stale() {
+            tracing::warn!("Rain nowcast from expired data.");
+        };
+
+        let minutely: Vec<_> = one_call
+            .minutely
+            .iter()
+            .filter(|minute| minute.dt + 60 > now)
+            .collect();
+        if minutely.is_empty() {
+            return Ok(if one_call.is_stale() {
+                String::from("No rain nowcast available, the weather service is unreachable.")
+            } else {
+                String::from("No rain nowcast available for this location.")
+            });
+        };
+
+        let minutes_from_now = |dt: u64| dt.saturating_sub(now) / 60;
+        let max = minutely
+            .iter()
+            .map(|minute| minute.precipitation)
+            .fold(0.0, f32::max);
+        let first_wet = minutely.iter().find(|minute| minute.precipitation > 0.0);
+
+        let reply = match first_wet {
+            None => String::from("No rain in the next hour."),
+            Some(first_wet) if minutes_from_now(first_wet.dt) == 0 => {
+                match minutely.iter().find(|minute| minute.precipitation == 0.0) {
+                    Some(first_dry) => format!(
+                        "Raining now, stops in {} min. Max {:.1} mm/h.",
+                        minutes_from_now(first_dry.dt),
+                        max
+                    ),
+                    None => format!("Rain for the next hour. Max {:.1} mm/h.", max),
+                }
+            }
+            Some(first_wet) => format!(
+                "Rain in {} min. Max {:.1} mm/h.",
+                minutes_from_now(first_wet.dt),
+                max
+            ),
+        };
+
+        Ok(reply + &Self::stale_note(one_call.stale_age))
+    }
+
+    async fn weather_alerts_reply(
+        &mut self,
+        coords: Option<Coordinates>,
+        localization: &Localization,
+    ) -> Result<String, Error> {
+        let (lat, lon) = self.coords_or_home(coords);
+        let one_call = self.owm_api.get_one_call(lat, lon, localization).await?;
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        let alerts: Vec<String> = one_call
+            .active_alerts(now)
+            .map(|alert| Self::format_weather_alert(&one_call, alert))
+            .collect();
+
+        let stale_note = Self::stale_note(one_call.stale_age);
+        if alerts.is_empty() {
+            Ok(String::from("No active weather alerts.") + &stale_note)
+        } else {
+            Ok(Self::fit_payload_with_suffix(
+                alerts.join("\n"),
+                &stale_note,
+            ))
+        }
+    }
+
+    /// Broadcast all active weather alerts for the bot location that were not broadcasted yet.
+    async fn broadcast_weather_alerts(&mut self) -> Result<(), Error> {
+        let (lat, lon) = self.coords_or_home(None);
+        let one_call = self
+            .owm_api
+            .get_one_call(lat, lon, &self.config.forecast.localization)
+            .await?;
+        let now = chrono::Utc::now().timestamp() as u64;
+
+        let active: Vec<&Alert> = one_call.active_alerts(now).collect();
+        self.weather_alerts_broadcasted.retain(|(event, start)| {
+            active
+                .iter()
+                .any(|alert| &alert.event == event && alert.start == *start)
+        });
+
+        for alert in active {
+            if !self
+                .weather_alerts_broadcasted
+                .insert((alert.event.clone(), alert.start))
+            {
+                continue;
+            };
+
+            tracing::info!("Weather alert: {:?}", alert);
+            let text = Self::fit_payload(Self::format_weather_alert(&one_call, alert));
+            let mut message =
+                OutgoingMessage::new(text.clone(), Target::PrimaryChannel, None, Priority::Alert);
+            message.on_sent = Some(SentMessage::alert(text));
+            self.enqueue(Outgoing::Message(message));
+        }
+
+        Ok(())
+    }
+
+    /// Broadcast the forecast for the bot location as environment telemetry.
+    ///
+    /// Expired forecasts are not broadcasted, as they would be shown as current measurements.
+    async fn broadcast_telemetry(&mut self) -> Result<(), Error> {
+        /// A full day of 3 hour segments for the 24h rainfall.
+        const DAY_SEGMENTS: u8 = 8;
+
+        let (lat, lon) = self.coords_or_home(None);
+        // Telemetry is always in metric units.
+        let localization = Localization::new(
+            Units::Metric,
+            self.config.forecast.localization.lang.clone(),
+        );
+        let forecast = self
+            .owm_api
+            .get_5day_3hour_forecast(lat, lon, Some(DAY_SEGMENTS), &localization)
+            .await?;
+        if forecast.is_stale() {
+            tracing::warn!("Skipping the telemetry broadcast: The forecast is expired.");
+            return Ok(());
+        };
+
+        let forecast = essential_forecast::Forecast::try_from(forecast.forecast)?;
+        let metrics = meshtastic::protobufs::EnvironmentMetrics::try_from(&forecast)?;
+        tracing::debug!("Broadcasting telemetry: {:?}", metrics);
+
+        self.enqueue(Outgoing::Telemetry(metrics));
+
         Ok(())
     }
+
+    /// Format a weather alert with the end time in the local time of the location.
+    fn format_weather_alert(one_call: &OneCall, alert: &Alert) -> String {
+        let end = chrono::FixedOffset::east_opt(one_call.timezone_offset)
+            .zip(chrono::DateTime::from_timestamp(alert.end as i64, 0))
+            .map(|(offset, end)| end.with_timezone(&offset).format("%a %H:%M").to_string())
+            .unwrap_or_default();
+
+        format!("⚠ {} until {} ({})", alert.event, end, alert.sender_name)
+    }
+
+    /// Tell how old the data of a reply is if it is an expired cached one. E.g. ` (data from 6h ago)`.
+    fn stale_note(stale_age: Option<chrono::TimeDelta>) -> String {
+        match stale_age {
+            Some(age) if age.num_hours() > 0 => format!(" (data from {}h ago)", age.num_hours()),
+            Some(age) => format!(" (data from {} min ago)", age.num_minutes()),
+            None => String::new(),
+        }
+    }
+
+    /// Split a text at the last line break that fits into a single message with the `more` hint.
+    ///
+    /// Texts without a fitting line break are truncated instead.
+    fn split_payload(text: String) -> (String, Option<String>) {
+        const MORE_HINT: &str = "\n…reply \"more\"";
+
+        if text.len() <= MAX_PAYLOAD_SIZE {
+            return (text, None);
+        };
+
+        let max_len = MAX_PAYLOAD_SIZE - MORE_HINT.len();
+        match text.as_bytes()[..max_len]
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+        {
+            Some(end) if end > 0 => (
+                format!("{}{}", &text[..end], MORE_HINT),
+                Some(text[end + 1..].to_string()),
+            ),
+            _ => (Self::fit_payload(text), None),
+        }
+    }
+
+    /// Truncate a text to fit into a single message.
+    fn fit_payload(text: String) -> String {
+        Self::fit_payload_with_suffix(text, "")
+    }
+
+    /// Truncate a text to fit into a single message with `suffix` appended.
+    fn fit_payload_with_suffix(mut text: String, suffix: &str) -> String {
+        const ELLIPSIS: char = '…';
+
+        let max_len = MAX_PAYLOAD_SIZE - suffix.len();
+        if text.len() > max_len {
+            let mut end = max_len - ELLIPSIS.len_utf8();
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            text.truncate(end);
+            text.push(ELLIPSIS);
+        };
+        text.push_str(suffix);
+
+        text
+    }
+
+    fn coords_or_home(&self, coords: Option<Coordinates>) -> (f64, f64) {
+        match coords {
+            Some(coords) => (coords.lat, coords.lon),
+            None => (self.config.location.lat, self.config.location.lon),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use meshtastic::protobufs;
+    use meshtastic_api::fake::FakeRadio;
+
+    use super::{
+        stub_server::{StubResponse, StubServer},
+        *,
+    };
+
+    const BOT_NODE_NUM: u32 = 0x1234;
+    const USER_NODE_NUM: u32 = 0x42;
+    const FORECAST_COUNT: usize = 16;
+
+    /// A bot connected to `radio` asking `server` instead of OWM, with all its state in a temporary directory.
+    async fn start_bot(name: &str, radio: &FakeRadio, server: &StubServer) -> Bot {
+        let dir = std::env::temp_dir().join(format!("bot-test-{}-{}", name, std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let key_path = dir.join("owm_api_key");
+        tokio::fs::write(&key_path, "test-key").await.unwrap();
+
+        let mut config = Config {
+            owm_api_key_file: Some(key_path.to_string_lossy().into_owned()),
+            node_preferences_path: dir
+                .join("node_preferences.json")
+                .to_string_lossy()
+                .into_owned(),
+            ..Default::default()
+        };
+        config.owm_http.base_url = server.base_url().to_string();
+        config.owm_budget.state_path = None;
+        config.forecast.cache_dir = None;
+        config.forecast.forecast_count = FORECAST_COUNT as u8;
+        config.meshtastic.max_delivery_retries = 0;
+
+        Bot::with_transport(config, radio.transport())
+            .await
+            .unwrap()
+    }
+
+    /// Run the bot until `conversation` is over.
+    async fn converse<T>(bot: &mut Bot, conversation: impl Future<Output = T>) -> T {
+        tokio::select! {
+            result = bot.run() => panic!("The bot stopped: {:?}", result),
+            output = tokio::time::timeout(Duration::from_secs(10), conversation) => {
+                output.expect("The bot did not answer in time")
+            }
+        }
+    }
+
+    /// The next text message the bot sent and its data.
+    async fn next_text(radio: &mut FakeRadio) -> (protobufs::MeshPacket, protobufs::Data) {
+        loop {
+            let mesh_packet = radio.next_sent_packet().await.unwrap();
+            if let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) =
+                &mesh_packet.payload_variant
+                && data.portnum == protobufs::PortNum::TextMessageApp as i32
+            {
+                let data = data.clone();
+                return (mesh_packet, data);
+            };
+        }
+    }
+
+    /// The recorded forecast with `count` 3 hour segments.
+    fn forecast_response(count: usize) -> StubResponse {
+        let mut forecast: serde_json::Value = serde_json::from_str(include_str!(
+            "../crates/open_weather_map_api/tests/owm_api/responses/forecast.json"
+        ))
+        .unwrap();
+        let first = forecast["list"][0].clone();
+        let first_dt = first["dt"].as_u64().unwrap();
+        forecast["list"] = (0..count as u64)
+            .map(|i| {
+                let mut segment = first.clone();
+                segment["dt"] = (first_dt + i * 3 * 3600).into();
+                segment
+            })
+            .collect();
+        forecast["cnt"] = count.into();
+
+        StubResponse::ok(forecast.to_string())
+    }
+
+    #[tokio::test]
+    async fn replies_to_text_message() {
+        let mut radio = FakeRadio::new(BOT_NODE_NUM);
+        let server = StubServer::start(vec![StubResponse::status(500)]).await;
+        let mut bot = start_bot("status", &radio, &server).await;
+
+        let request_id =
+            radio.inject_text(USER_NODE_NUM, Target::NodeId(BOT_NODE_NUM), 0, "status");
+        let (reply, data) = converse(&mut bot, next_text(&mut radio)).await;
+
+        assert_eq!(reply.to, USER_NODE_NUM);
+        assert_eq!(data.reply_id, request_id);
+        assert!(
+            String::from_utf8(data.payload)
+                .unwrap()
+                .starts_with("OWM calls left")
+        );
+    }
+
+    #[tokio::test]
+    async fn continues_long_forecast_on_more() {
+        let mut radio = FakeRadio::new(BOT_NODE_NUM);
+        let server = StubServer::start(vec![forecast_response(FORECAST_COUNT)]).await;
+        let mut bot = start_bot("forecast", &radio, &server).await;
+
+        radio.inject_text(USER_NODE_NUM, Target::NodeId(BOT_NODE_NUM), 0, "wx");
+        let texts = converse(&mut bot, async {
+            let mut texts = Vec::new();
+            loop {
+                let (reply, data) = next_text(&mut radio).await;
+                assert_eq!(reply.to, USER_NODE_NUM);
+                let text = String::from_utf8(data.payload).unwrap();
+                assert!(text.len() <= MAX_PAYLOAD_SIZE, "Too long: {:?}", text);
+
+                let more = text.ends_with("reply \"more\"");
+                texts.push(text);
+                if !more {
+                    return texts;
+                };
+                radio.inject_reply(
+                    USER_NODE_NUM,
+                    Target::NodeId(BOT_NODE_NUM),
+                    reply.id,
+                    "more",
+                );
+            }
+        })
+        .await;
+
+        assert!(texts.len() > 1);
+        let forecast_lines = texts
+            .iter()
+            .flat_map(|text| text.lines())
+            .filter(|line| line.contains("°C"))
+            .count();
+        assert_eq!(forecast_lines, FORECAST_COUNT);
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn answers_usage_only_in_direct_messages() {
+        let mut radio = FakeRadio::new(BOT_NODE_NUM);
+        let server = StubServer::start(vec![StubResponse::status(500)]).await;
+        let mut bot = start_bot("usage", &radio, &server).await;
+
+        radio.inject_node_info(USER_NODE_NUM, "ALC", "Alice");
+        radio.inject_text(
+            USER_NODE_NUM,
+            Target::PrimaryChannel,
+            0,
+            "rain is coming tonight",
+        );
+        radio.inject_text(USER_NODE_NUM, Target::PrimaryChannel, 0, "status");
+        radio.inject_text(
+            USER_NODE_NUM,
+            Target::NodeId(BOT_NODE_NUM),
+            0,
+            "rain tonight",
+        );
+        let (channel_reply, direct_reply) = converse(&mut bot, async {
+            (
+                radio.next_sent_text().await.unwrap(),
+                radio.next_sent_text().await.unwrap(),
+            )
+        })
+        .await;
+
+        assert!(matches!(channel_reply.0, Target::PrimaryChannel));
+        assert!(channel_reply.1.starts_with("@ALC OWM calls left"));
+        assert!(matches!(direct_reply.0, Target::NodeId(USER_NODE_NUM)));
+        assert_eq!(direct_reply.1, "Usage: rain [<lat> <lon>]");
+    }
+
+    #[tokio::test]
+    async fn sends_queued_messages_after_reconnect() {
+        let mut radio = FakeRadio::new(BOT_NODE_NUM);
+        let server = StubServer::start(vec![StubResponse::status(500)]).await;
+        let mut bot = start_bot("reconnect", &radio, &server).await;
+
+        radio.drop_connection();
+        bot.meshtastic_api.disconnected().await;
+        assert!(!bot.meshtastic_api.is_connected());
+
+        bot.send_continued(OutgoingMessage::new(
+            String::from("queued"),
+            Target::NodeId(USER_NODE_NUM),
+            None,
+            Priority::Reply,
+        ));
+        assert_eq!(bot.scheduler.depth(), 1);
+
+        let (target, text) = converse(&mut bot, radio.next_sent_text()).await.unwrap();
+
+        assert!(matches!(target, Target::NodeId(USER_NODE_NUM)));
+        assert_eq!(text, "queued");
+        assert!(bot.meshtastic_api.is_connected());
+        assert_eq!(bot.scheduler.depth(), 0);
+    }
 }
@@ -0,0 +1,147 @@
+use chrono::Timelike;
+use open_weather_map_api::localization::Units;
+use serde::{Deserialize, Serialize};
+
+use crate::preferences::Subscription;
+
+/// A command a node can send to the bot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Weather forecast.
+    ///
+    /// `wx [<lat> <lon>]`
+    Forecast(Option<Coordinates>),
+    /// Current air quality and the worst forecasted air quality for the next 24 hours.
+    ///
+    /// `air [<lat> <lon>]`
+    Air(Option<Coordinates>),
+    /// Precipitation nowcast for the next hour.
+    ///
+    /// `rain [<lat> <lon>]`
+    Rain(Option<Coordinates>),
+    /// Active official weather alerts.
+    ///
+    /// `alerts [<lat> <lon>]`
+    Alerts(Option<Coordinates>),
+    /// State of the bot, e.g. the remaining API calls.
+    ///
+    /// `status`
+    Status,
+    /// Set the preferred units of the node.
+    ///
+    /// `units <metric|imperial|standard>`
+    Units(Units),
+    /// Set the preferred language of the node.
+    ///
+    /// `lang <code>`
+    Lang(String),
+    /// Receive the forecast every day at a time of the bot's clock.
+    ///
+    /// `sub <HH:MM> [<lat> <lon>]`
+    Subscribe(Subscription),
+    /// Stop the daily forecast.
+    ///
+    /// `unsub`
+    Unsubscribe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ParseError {
+    #[error("Unknown command")]
+    Unknown,
+    #[error("Usage: {0}")]
+    Usage(&'static str),
+}
+
+impl Command {
+    /// Parse a text message into a `Command`.
+    ///
+    /// Returns `ParseError::Unknown` if the message is not meant for the bot
+    /// and `ParseError::Usage` if the arguments are invalid.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut words = text.split_whitespace();
+        let Some(keyword) = words.next() else {
+            return Err(ParseError::Unknown);
+        };
+        let args: Vec<&str> = words.collect();
+
+        match keyword.to_lowercase().as_str() {
+            "wx" => Ok(Self::Forecast(
+                Coordinates::parse_args(&args).ok_or(ParseError::Usage("wx [<lat> <lon>]"))?,
+            )),
+            "air" => Ok(Self::Air(
+                Coordinates::parse_args(&args).ok_or(ParseError::Usage("air [<lat> <lon>]"))?,
+            )),
+            "rain" => Ok(Self::Rain(
+                Coordinates::parse_args(&args).ok_or(ParseError::Usage("rain [<lat> <lon>]"))?,
+            )),
+            "alerts" => Ok(Self::Alerts(
+                Coordinates::parse_args(&args).ok_or(ParseError::Usage("alerts [<lat> <lon>]"))?,
+            )),
+            "status" => Ok(Self::Status),
+            "units" => match args[..] {
+                [units] => {
+                    Ok(Self::Units(units.parse().map_err(|_| {
+                        ParseError::Usage("units <metric|imperial|standard>")
+                    })?))
+                }
+                _ => Err(ParseError::Usage("units <metric|imperial|standard>")),
+            },
+            "lang" => match args[..] {
+                [lang] if Self::is_lang_code(lang) => Ok(Self::Lang(lang.to_lowercase())),
+                _ => Err(ParseError::Usage("lang <code>, e.g. lang en")),
+            },
+            "sub" => {
+                const USAGE: &str = "sub <HH:MM> [<lat> <lon>], e.g. sub 07:00";
+
+                let [time, coords @ ..] = &args[..] else {
+                    return Err(ParseError::Usage(USAGE));
+                };
+                let time = chrono::NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| ParseError::Usage(USAGE))?;
+
+                Ok(Self::Subscribe(Subscription {
+                    hour: time.hour(),
+                    minute: time.minute(),
+                    coords: Coordinates::parse_args(coords).ok_or(ParseError::Usage(USAGE))?,
+                }))
+            }
+            "unsub" => Ok(Self::Unsubscribe),
+            _ => Err(ParseError::Unknown),
+        }
+    }
+
+    /// OWM language codes are 2 letters with an optional region. E.g. `en`, `zh_cn`, `pt_br`.
+    fn is_lang_code(lang: &str) -> bool {
+        (2..=5).contains(&lang.len())
+            && lang.chars().all(|ch| ch.is_ascii_alphabetic() || ch == '_')
+    }
+}
+
+impl Coordinates {
+    /// Parse `<lat> <lon>` arguments.
+    ///
+    /// Returns `Some(None)` if there are no arguments and `None` if they are invalid.
+    fn parse_args(args: &[&str]) -> Option<Option<Self>> {
+        match args {
+            [] => Some(None),
+            [lat, lon] => {
+                let lat: f64 = lat.trim_end_matches(',').parse().ok()?;
+                let lon: f64 = lon.parse().ok()?;
+
+                if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+                    Some(Some(Self { lat, lon }))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use meshtastic_api::{channel::Channel, packet::Target, scheduler::Priority};
+
+use super::sent::SentMessage;
+
+/// A send waiting in the outbound queue.
+#[derive(Debug)]
+pub enum Outgoing {
+    Message(OutgoingMessage),
+    /// An emoji reaction to the packet `reply_id`.
+    Reaction {
+        emoji: String,
+        reply_id: u32,
+        target: Target,
+        channel: Option<Channel>,
+    },
+    Telemetry(meshtastic::protobufs::EnvironmentMetrics),
+}
+
+/// A message sent with delivery tracking, kept to send it again if it was not delivered.
+#[derive(Debug, Clone)]
+pub struct OutgoingMessage {
+    pub text: String,
+    pub target: Target,
+    pub channel: Option<Channel>,
+    pub priority: Priority,
+    /// Remembered by the packet id once sent, to handle reactions and replies to it.
+    pub on_sent: Option<SentMessage>,
+    /// The id of the packet this message answers.
+    pub reply_id: Option<u32>,
+    /// How often the message was sent again.
+    pub retry: u32,
+}
+
+/// The delivery outcomes of the messages to a node.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeliveryStats {
+    pub delivered: u32,
+    pub failed: u32,
+}
+
+impl OutgoingMessage {
+    pub fn new(text: String, target: Target, channel: Option<Channel>, priority: Priority) -> Self {
+        Self {
+            text,
+            target,
+            channel,
+            priority,
+            on_sent: None,
+            reply_id: None,
+            retry: 0,
+        }
+    }
+
+    /// Thread the message as a reply to the packet `reply_id`.
+    pub fn replying_to(mut self, reply_id: u32) -> Self {
+        self.reply_id = Some(reply_id);
+        self
+    }
+}
+
+impl Outgoing {
+    pub fn priority(&self) -> Priority {
+        match self {
+            Self::Message(message) => message.priority,
+            Self::Reaction { .. } => Priority::Reply,
+            Self::Telemetry(_) => Priority::Telemetry,
+        }
+    }
+
+    /// The size of the payload, to estimate the airtime.
+    pub fn payload_len(&self) -> usize {
+        use meshtastic::Message;
+
+        /// The time and variant tag of the telemetry around the metrics.
+        const TELEMETRY_OVERHEAD: usize = 8;
+
+        match self {
+            Self::Message(message) => message.text.len(),
+            Self::Reaction { emoji, .. } => emoji.len(),
+            Self::Telemetry(metrics) => metrics.encoded_len() + TELEMETRY_OVERHEAD,
+        }
+    }
+}
+
+impl DeliveryStats {
+    pub fn record(&mut self, delivered: bool) {
+        if delivered {
+            self.delivered += 1;
+        } else {
+            self.failed += 1;
+        };
+    }
+
+    /// The delivered share of all messages, 0.0 - 1.0.
+    pub fn rate(&self) -> f32 {
+        let total = self.delivered + self.failed;
+        if total == 0 {
+            return 0.0;
+        };
+
+        self.delivered as f32 / total as f32
+    }
+}
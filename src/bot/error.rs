@@ -2,10 +2,30 @@
 pub enum Error {
     #[error("Bot Config Error: {0}")]
     Config(#[from] crate::config::error::Error),
+    #[error("Node Preferences Error: {0}")]
+    Preferences(#[from] crate::preferences::error::Error),
     #[error("Open Weather Map API Key Environmen Variable Error: {}", 0)]
     OpenWeatherMapApiKeyPath(#[from] std::env::VarError),
+    #[error("Open Weather Map API Key File Error: {0}")]
+    OpenWeatherMapApiKeyFile(std::io::Error),
     #[error("Meshtastic API Error: {0}")]
     MeshtasticApi(#[from] meshtastic_api::error::Error),
+    #[error("Meshtastic Send Error: {0}")]
+    MeshtasticSend(#[from] meshtastic_api::error::SendError),
+    #[error("Open Weather Map API Error: {0}")]
+    OpenWeatherMapApi(#[from] open_weather_map_api::error::Error),
+    #[error("Forecast Error: {0}")]
+    EssentialForecast(#[from] crate::essential_forecast::error::Error),
     #[error("Tokio Serial Error: {0}")]
     TokioSerial(#[from] tokio_serial::Error),
 }
+
+impl Error {
+    /// Errors the bot can not recover from without a configuration change.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            Self::OpenWeatherMapApi(e) => e.is_fatal(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use open_weather_map_api::localization::Localization;
+
+/// Plans which subscribed locations to fetch ahead so scheduled forecasts are served from the cache.
+///
+/// Locations are prefetched within `lead` before their delivery, earliest delivery first and at most
+/// `max_per_check` per check, which spreads the requests over the lead time.
+#[derive(Debug)]
+pub struct PrefetchPlanner {
+    lead: chrono::TimeDelta,
+    max_per_check: usize,
+    /// Prefetches done for deliveries that are still ahead.
+    prefetched: HashSet<Prefetch>,
+}
+
+/// A cache cell to fetch for a delivery.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Prefetch {
+    pub delivery: chrono::DateTime<chrono::Local>,
+    /// The bits of the cell center coordinates to be hashable.
+    cell: (u64, u64),
+    pub localization: Localization,
+}
+
+impl PrefetchPlanner {
+    pub fn new(lead: chrono::TimeDelta, max_per_check: usize) -> Self {
+        Self {
+            lead,
+            max_per_check,
+            prefetched: HashSet::new(),
+        }
+    }
+
+    /// Choose the prefetches to do now out of the `upcoming` deliveries.
+    ///
+    /// Returns at most `budget` prefetches, which are considered done.
+    pub fn plan(
+        &mut self,
+        now: chrono::DateTime<chrono::Local>,
+        upcoming: impl IntoIterator<Item = Prefetch>,
+        budget: usize,
+    ) -> Vec<Prefetch> {
+        self.prefetched.retain(|prefetch| prefetch.delivery > now);
+
+        let due: HashSet<Prefetch> = upcoming
+            .into_iter()
+            .filter(|prefetch| {
+                prefetch.delivery > now
+                    && prefetch.delivery - now <= self.lead
+                    && !self.prefetched.contains(prefetch)
+            })
+            .collect();
+
+        let mut due: Vec<Prefetch> = due.into_iter().collect();
+        due.sort_by_key(|prefetch| prefetch.delivery);
+        due.truncate(self.max_per_check.min(budget));
+
+        self.prefetched.extend(due.iter().cloned());
+
+        due
+    }
+}
+
+impl Prefetch {
+    /// Prefetch the cache cell with the center `cell_center`.
+    pub fn new(
+        delivery: chrono::DateTime<chrono::Local>,
+        (lat, lon): (f64, f64),
+        localization: Localization,
+    ) -> Self {
+        Self {
+            delivery,
+            cell: (lat.to_bits(), lon.to_bits()),
+            localization,
+        }
+    }
+
+    /// The center of the cache cell.
+    pub fn coords(&self) -> (f64, f64) {
+        (f64::from_bits(self.cell.0), f64::from_bits(self.cell.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::preferences::Subscription;
+
+    const BERLIN: (f64, f64) = (52.52, 13.405);
+    const HAMBURG: (f64, f64) = (53.55, 9.993);
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::DateTime<chrono::Local> {
+        chrono::Local
+            .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn planner() -> PrefetchPlanner {
+        PrefetchPlanner::new(chrono::TimeDelta::minutes(30), 8)
+    }
+
+    fn prefetch(delivery: chrono::DateTime<chrono::Local>, cell: (f64, f64)) -> Prefetch {
+        Prefetch::new(delivery, cell, Localization::default())
+    }
+
+    /// The prefetch of the next delivery of a subscription at `hour:minute`.
+    fn subscribed(now: chrono::DateTime<chrono::Local>, hour: u32, minute: u32) -> Prefetch {
+        let subscription = Subscription {
+            hour,
+            minute,
+            coords: None,
+        };
+
+        prefetch(subscription.next_delivery(now).unwrap(), BERLIN)
+    }
+
+    #[test]
+    fn prefetches_within_lead() {
+        let mut planner = planner();
+        let now = at(10, 7, 0);
+
+        let planned = planner.plan(
+            now,
+            [subscribed(now, 7, 20), subscribed(now, 8, 0)],
+            usize::MAX,
+        );
+        assert_eq!(planned, vec![prefetch(at(10, 7, 20), BERLIN)]);
+    }
+
+    #[test]
+    fn lead_crosses_into_previous_day() {
+        let mut planner = planner();
+        let now = at(10, 23, 50);
+
+        let planned = planner.plan(now, [subscribed(now, 0, 10)], usize::MAX);
+        assert_eq!(planned, vec![prefetch(at(11, 0, 10), BERLIN)]);
+
+        // The delivery is not prefetched again after midnight.
+        let now = at(11, 0, 5);
+        assert!(
+            planner
+                .plan(now, [subscribed(now, 0, 10)], usize::MAX)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn deduplicates_subscriptions_in_same_cell() {
+        let mut planner = planner();
+        let now = at(10, 7, 0);
+
+        let planned = planner.plan(
+            now,
+            [
+                prefetch(at(10, 7, 20), BERLIN),
+                prefetch(at(10, 7, 20), BERLIN),
+                prefetch(at(10, 7, 20), HAMBURG),
+                prefetch(at(10, 7, 25), BERLIN),
+            ],
+            usize::MAX,
+        );
+        assert_eq!(
+            planned.len(),
+            3,
+            "One prefetch per cell and delivery: {:?}",
+            planned
+        );
+    }
+
+    #[test]
+    fn earliest_delivery_first_within_limits() {
+        let mut planner = PrefetchPlanner::new(chrono::TimeDelta::minutes(30), 2);
+        let now = at(10, 7, 0);
+        let upcoming = [
+            prefetch(at(10, 7, 25), BERLIN),
+            prefetch(at(10, 7, 5), HAMBURG),
+            prefetch(at(10, 7, 15), BERLIN),
+        ];
+
+        let planned = planner.plan(now, upcoming.clone(), usize::MAX);
+        assert_eq!(planned, vec![upcoming[1].clone(), upcoming[2].clone()]);
+
+        let planned = planner.plan(now, upcoming.clone(), 0);
+        assert!(planned.is_empty());
+
+        let planned = planner.plan(now, upcoming.clone(), 1);
+        assert_eq!(planned, vec![upcoming[0].clone()]);
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::{HashSet, VecDeque};
+
+/// The recent messages of the bot that nodes may react or reply to, by packet id.
+///
+/// Only the last `CAPACITY` messages are kept, older ones are forgotten.
+#[derive(Debug, Default)]
+pub struct SentMessages {
+    messages: VecDeque<(u32, SentMessage)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SentMessage {
+    /// A broadcasted alert and the nodes that acknowledged it with a reaction.
+    Alert {
+        text: String,
+        acknowledged_by: HashSet<u32>,
+    },
+    /// A reply that did not fit into one message, with the rest sent on `more`.
+    Continued(String),
+}
+
+impl SentMessages {
+    const CAPACITY: usize = 64;
+
+    pub fn record(&mut self, id: u32, message: SentMessage) {
+        if self.messages.len() >= Self::CAPACITY {
+            self.messages.pop_front();
+        };
+
+        self.messages.push_back((id, message));
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut SentMessage> {
+        self.messages
+            .iter_mut()
+            .find(|(sent_id, _)| *sent_id == id)
+            .map(|(_, message)| message)
+    }
+
+    /// Take the rest of the reply `id`, if it was continued.
+    pub fn take_continuation(&mut self, id: u32) -> Option<String> {
+        let position = self.messages.iter().position(|(sent_id, message)| {
+            *sent_id == id && matches!(message, SentMessage::Continued(_))
+        })?;
+
+        match self.messages.remove(position) {
+            Some((_, SentMessage::Continued(rest))) => Some(rest),
+            _ => None,
+        }
+    }
+}
+
+impl SentMessage {
+    pub fn alert(text: String) -> Self {
+        Self::Alert {
+            text,
+            acknowledged_by: HashSet::new(),
+        }
+    }
+}
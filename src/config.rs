@@ -1,37 +1,158 @@
 use std::path::Path;
 
+use open_weather_map_api::{
+    air_pollution::Aqi, budget::BudgetConfig, http::HttpConfig, localization::Localization,
+};
 use serde::{Deserialize, Serialize};
 
 pub mod error;
 
 use error::Error;
 
+/// Missing fields fall back to their defaults, so configs written by older versions keep loading.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub owm_api_key_env_var: String,
+    /// Read the OWM API key from this file instead of `owm_api_key_env_var`.
+    ///
+    /// Relative paths are resolved against `$CREDENTIALS_DIRECTORY` if set, so a systemd
+    /// `LoadCredential=owm_api_key:...` can be used with `owm_api_key_file = "owm_api_key"`.
+    pub owm_api_key_file: Option<String>,
+    /// Where the units and languages chosen by the nodes are stored.
+    pub node_preferences_path: String,
+    /// HTTP client settings for the OWM API.
+    pub owm_http: HttpConfig,
+    /// Limits for the OWM API calls. Set them to the limits of your OWM plan.
+    pub owm_budget: BudgetConfig,
+    pub location: Location,
     pub forecast: Forecast,
+    pub air_quality: AirQuality,
+    pub weather_alerts: WeatherAlerts,
+    pub subscriptions: Subscriptions,
+    pub telemetry: Telemetry,
     pub meshtastic: Meshtastic,
 }
 
+/// The location the bot reports for when no location is requested.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Location {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Forecast {
     /// How many units of forecast time should be fetched.
     pub forecast_count: u8,
     /// The time to live in seconds for cached forecasts.
     pub cache_ttl_s: u32,
+    /// The size of the cache grid cells in km. Nodes within the same cell share cached forecasts.
+    pub cache_grid_km: f64,
     /// Controls how often the cache gets cleaned.
     ///
     /// The cache cleans itself every time this value is hit.
     pub soft_cache_limit: usize,
+    /// The maximum number of cached forecasts per kind. The least recently used one is evicted to make room.
+    pub cache_capacity: usize,
+    /// How long in seconds an expired forecast is kept to answer requests when the OWM API is unreachable.
+    pub max_stale_age_s: u32,
+    /// The directory the forecast caches are saved to so they survive restarts. Unset keeps them in memory only.
+    pub cache_dir: Option<String>,
+    /// The units and language used when a node did not choose its own.
+    pub localization: Localization,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AirQuality {
+    /// Broadcast an alert on the primary channel when the AQI is forecast to reach this level.
+    ///
+    /// 1 = Good, 2 = Fair, 3 = Moderate, 4 = Poor, 5 = Very Poor. Unset disables alerts.
+    pub alert_threshold: Option<Aqi>,
+    /// How often the air pollution forecast is checked for alerts in seconds.
+    pub alert_check_interval_s: u32,
+}
+
+/// Official weather alerts relayed by the OWM One Call 3.0 API.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeatherAlerts {
+    /// Broadcast new alerts for the bot location on the primary channel.
+    ///
+    /// Requires a One Call 3.0 subscription.
+    pub broadcast: bool,
+    /// How often the alerts are checked in seconds.
+    pub check_interval_s: u32,
+}
+
+/// Daily forecasts the nodes subscribed to with `sub`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Subscriptions {
+    /// How often due subscriptions are checked in seconds.
+    pub check_interval_s: u32,
+    /// How long in seconds before a delivery its forecast is fetched into the cache.
+    pub prefetch_lead_s: u32,
+    /// The maximum number of forecasts prefetched per check, to spread the API calls over the lead time.
+    pub max_prefetches_per_check: usize,
 }
 
+/// The forecast for the bot location sent as environment telemetry, so nodes show the bot as a weather station.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    /// Broadcast the telemetry on the primary channel.
+    pub broadcast: bool,
+    /// How often the telemetry is sent in seconds.
+    pub interval_s: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Meshtastic {
     /// The serial port of the meshtastic radio.
     ///
     /// Use `dmesg | grep tty` and the `info` message to choose one.
     pub serial_path: String,
+    /// The address of a WiFi or Ethernet radio, e.g. `192.168.1.20` or `meshtastic.local:4403`.
+    ///
+    /// Used instead of `serial_path` if set. The port defaults to 4403.
+    pub tcp_address: Option<String>,
+    /// The USB vendor id of the radio, e.g. `0x239a`. Used with `usb_pid` instead of `serial_path` if set.
+    ///
+    /// The serial port is looked up on every reconnect, so the radio may be replugged.
+    pub usb_vid: Option<u16>,
+    /// The USB product id of the radio.
+    pub usb_pid: Option<u16>,
+    /// Replay this capture of the radio stream instead of connecting to a radio. Used to reproduce field bugs.
+    pub replay_path: Option<String>,
+    /// How many times faster than recorded the capture is replayed. 0 replays as fast as possible.
+    pub replay_speed: u32,
+    /// Append every packet received from the radio to this file. See `replay_path`.
+    pub capture_path: Option<String>,
     pub packet_buffer: usize,
+    /// How long to wait for the acknowledgement of a sent message in seconds.
+    pub delivery_timeout_s: u32,
+    /// How often a message that was not delivered is sent again.
+    pub max_delivery_retries: u32,
+    /// The wait before the first reconnect attempt after the radio link is lost in seconds.
+    ///
+    /// Doubled after every failed attempt up to `reconnect_max_backoff_s`.
+    pub reconnect_initial_backoff_s: u32,
+    pub reconnect_max_backoff_s: u32,
+    /// How many sends may wait for the radio to reconnect or for airtime. The newest of the lowest priority are dropped first.
+    pub outbox_capacity: usize,
+    /// The share of airtime the bot may use in percent.
+    ///
+    /// EU868 allows 1%, 10% on the 869.4 - 869.65 MHz sub-band. Stay below to leave the shared mesh room.
+    pub duty_cycle_percent: f32,
+    /// The sliding window the duty cycle is measured over in seconds.
+    pub duty_cycle_window_s: u32,
+    /// React to every command with this emoji as a receipt, e.g. `👀`. Unset sends no receipts to save airtime.
+    pub receipt_emoji: Option<String>,
 }
 
 impl Config {
@@ -56,13 +177,41 @@ impl Config {
     /// Read the config from file.
     pub async fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
         let config_string = tokio::fs::read_to_string(path).await?;
-        let config = toml::from_str(&config_string)?;
+        let config: Self = toml::from_str(&config_string)?;
+        config.validate()?;
 
         tracing::debug!("Read config");
 
         Ok(config)
     }
 
+    /// Reject the values serde accepts but the bot cannot run with, e.g. intervals of 0 that would panic.
+    fn validate(&self) -> Result<(), Error> {
+        let intervals = [
+            (
+                "air_quality.alert_check_interval_s",
+                self.air_quality.alert_check_interval_s,
+            ),
+            (
+                "weather_alerts.check_interval_s",
+                self.weather_alerts.check_interval_s,
+            ),
+            (
+                "subscriptions.check_interval_s",
+                self.subscriptions.check_interval_s,
+            ),
+            ("telemetry.interval_s", self.telemetry.interval_s),
+        ];
+
+        match intervals
+            .into_iter()
+            .find(|(_, interval_s)| *interval_s == 0)
+        {
+            Some((name, _)) => Err(Error::ZeroInterval(name)),
+            None => Ok(()),
+        }
+    }
+
     /// Write the config to file.
     pub async fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
         let config_string = toml::to_string_pretty(self)?;
@@ -78,15 +227,160 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             owm_api_key_env_var: "OWM_API_KEY".to_string(),
-            forecast: Forecast {
-                forecast_count: 6,
-                cache_ttl_s: 10800,
-                soft_cache_limit: 32,
-            },
-            meshtastic: Meshtastic {
-                serial_path: String::from("/dev/ttyEXAMPLE"),
-                packet_buffer: 4,
+            owm_api_key_file: None,
+            node_preferences_path: String::from("./node_preferences.json"),
+            owm_http: HttpConfig::default(),
+            owm_budget: BudgetConfig {
+                state_path: Some(String::from("./owm_budget.json")),
+                ..Default::default()
             },
+            location: Location::default(),
+            forecast: Forecast::default(),
+            air_quality: AirQuality::default(),
+            weather_alerts: WeatherAlerts::default(),
+            subscriptions: Subscriptions::default(),
+            telemetry: Telemetry::default(),
+            meshtastic: Meshtastic::default(),
+        }
+    }
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self {
+            lat: 52.52,
+            lon: 13.405,
+        }
+    }
+}
+
+impl Default for Forecast {
+    fn default() -> Self {
+        Self {
+            forecast_count: 6,
+            cache_ttl_s: 10800,
+            cache_grid_km: 2.0,
+            soft_cache_limit: 32,
+            cache_capacity: 256,
+            max_stale_age_s: 172800,
+            cache_dir: Some(String::from("./cache")),
+            localization: Localization::default(),
         }
     }
 }
+
+impl Default for AirQuality {
+    fn default() -> Self {
+        Self {
+            alert_threshold: None,
+            alert_check_interval_s: 3600,
+        }
+    }
+}
+
+impl Default for WeatherAlerts {
+    fn default() -> Self {
+        Self {
+            broadcast: false,
+            check_interval_s: 900,
+        }
+    }
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        Self {
+            check_interval_s: 60,
+            prefetch_lead_s: 1800,
+            max_prefetches_per_check: 5,
+        }
+    }
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            broadcast: false,
+            interval_s: 1800,
+        }
+    }
+}
+
+impl Default for Meshtastic {
+    fn default() -> Self {
+        Self {
+            serial_path: String::from("/dev/ttyEXAMPLE"),
+            tcp_address: None,
+            usb_vid: None,
+            usb_pid: None,
+            replay_path: None,
+            replay_speed: 1,
+            capture_path: None,
+            packet_buffer: 4,
+            delivery_timeout_s: 120,
+            max_delivery_retries: 2,
+            reconnect_initial_backoff_s: 1,
+            reconnect_max_backoff_s: 60,
+            outbox_capacity: 32,
+            duty_cycle_percent: 10.0,
+            duty_cycle_window_s: 3600,
+            receipt_emoji: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_config_without_new_sections() {
+        let config: Config = toml::from_str(
+            r#"
+            owm_api_key_env_var = "OWM_API_KEY"
+
+            [forecast]
+            forecast_count = 4
+            cache_ttl_s = 3600
+            soft_cache_limit = 16
+
+            [meshtastic]
+            serial_path = "/dev/ttyUSB0"
+            packet_buffer = 8
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.forecast.forecast_count, 4);
+        assert_eq!(
+            config.forecast.cache_capacity,
+            Forecast::default().cache_capacity
+        );
+        assert_eq!(config.meshtastic.serial_path, "/dev/ttyUSB0");
+        assert_eq!(
+            config.meshtastic.outbox_capacity,
+            Meshtastic::default().outbox_capacity
+        );
+        assert_eq!(
+            config.owm_budget.state_path,
+            Config::default().owm_budget.state_path
+        );
+    }
+
+    #[test]
+    fn rejects_zero_intervals() {
+        let config: Config = toml::from_str(
+            r#"
+            [telemetry]
+            interval_s = 0
+            "#,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            config.validate(),
+            Err(Error::ZeroInterval("telemetry.interval_s"))
+        ));
+        assert!(Config::default().validate().is_ok());
+    }
+}
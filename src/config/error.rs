@@ -6,4 +6,6 @@ pub enum Error {
     TomlSer(#[from] toml::ser::Error),
     #[error("Toml Deserialize Error: {0}")]
     TomlDe(#[from] toml::de::Error),
+    #[error("{0} must not be 0")]
+    ZeroInterval(&'static str),
 }
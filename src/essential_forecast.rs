@@ -2,6 +2,10 @@ use std::ops::Deref;
 
 use serde::{Deserialize, Serialize};
 
+pub mod error;
+
+use error::Error;
+
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub enum Forecast {
     Hour3(Vec<ForecastSegment>),
@@ -40,9 +44,9 @@ pub struct ForecastSegment {
     pub pop: f32,
     /// Day/Night
     pub day_time: DayTime,
-    /// Rain volume in cm/X hours.
+    /// Rain volume in mm/3 hours.
     pub rain: Option<f32>,
-    /// Snow volume in cm/X hours.
+    /// Snow volume in mm/3 hours.
     pub snow: Option<f32>,
 }
 
@@ -69,6 +73,8 @@ pub enum Weather {
     Atmosphere(Atmosphere),
     Clear(String),
     Clouds(String),
+    /// A condition code this bot does not know yet, with its description.
+    Unknown(u16, String),
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -83,6 +89,8 @@ pub enum Atmosphere {
     Ash(String),
     Squall(String),
     Tornado(String),
+    /// An atmosphere code this bot does not know yet, with its description.
+    Unknown(u16, String),
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Serialize, Deserialize)]
@@ -104,15 +112,17 @@ pub enum DayTime {
     Night,
 }
 
-impl From<open_weather_map_api::forecast::Forecast> for Forecast {
-    fn from(lfc: open_weather_map_api::forecast::Forecast) -> Self {
+impl TryFrom<open_weather_map_api::forecast::Forecast> for Forecast {
+    type Error = Error;
+
+    fn try_from(lfc: open_weather_map_api::forecast::Forecast) -> Result<Self, Self::Error> {
         let mut forecast: Vec<ForecastSegment> = lfc
             .list
             .into_iter()
             .map(|fc| {
                 let fc_main = fc.main;
 
-                ForecastSegment {
+                Ok(ForecastSegment {
                     date_time: fc.dt,
                     date_time_txt: fc.dt_txt,
                     temp: Temp {
@@ -146,53 +156,43 @@ impl From<open_weather_map_api::forecast::Forecast> for Forecast {
                     },
                     visibility: fc.visibility,
                     pop: fc.pop,
-                    day_time: DayTime::from(fc.sys.pod),
+                    day_time: DayTime::try_from(fc.sys.pod)?,
                     rain: fc.rain.map(|r| r.three_hours),
                     snow: fc.snow.map(|r| r.three_hours),
-                }
+                })
             })
-            .collect();
+            .collect::<Result<_, Error>>()?;
         forecast.shrink_to_fit();
 
-        Self::Hour3(forecast)
+        Ok(Self::Hour3(forecast))
     }
 }
 
-impl From<&Forecast> for meshtastic::protobufs::EnvironmentMetrics {
-    /// Input a full day forecast to get the 24h rainfall forecast metric.
-    fn from(forecast: &Forecast) -> Self {
-        let forecast_segments: Vec<ForecastSegment> = match forecast {
-            Forecast::Hour3(fc) => {
-                if fc.len() >= 8 {
-                    fc[..8].iter().cloned().collect()
-                } else {
-                    vec![fc.get(0).expect("Failed to access forecast for environment metrics update. No segments available.").clone()]
-                }
-            }
-        };
+impl TryFrom<&Forecast> for meshtastic::protobufs::EnvironmentMetrics {
+    type Error = Error;
 
-        let (fcs, rainfall_1h) = match forecast {
-            Forecast::Hour3(fc) => {
-                let fcs = fc[0].clone();
+    /// Metrics of the first segment. Input a full day forecast to get the 24h rainfall forecast metric.
+    ///
+    /// Requires metric units: °C, hPa, m/s and mm.
+    fn try_from(forecast: &Forecast) -> Result<Self, Self::Error> {
+        /// 3 hour segments per day.
+        const DAY_SEGMENTS: usize = 8;
 
-                (fcs.clone(), fcs.rain.map_or(None, |rain| Some(rain / 3.0)))
-            }
-        };
-
-        let rainfall_24h =
-            forecast_segments
-                .into_iter()
-                .fold(None, |acc: Option<f32>, fc: ForecastSegment| {
-                    if let Some(rain) = fc.rain {
-                        acc.map_or_else(|| Some(rain), |rain_prev| Some(rain_prev + rain))
-                    } else {
-                        acc
-                    }
-                });
-
-        meshtastic::protobufs::EnvironmentMetrics {
+        let Forecast::Hour3(segments) = forecast;
+        let fcs = segments.first().ok_or(Error::NoSegments)?;
+
+        // The 3 hour volume averaged to 1 hour.
+        let rainfall_1h = fcs.rain.unwrap_or_default() / 3.0;
+        let rainfall_24h = (segments.len() >= DAY_SEGMENTS).then(|| {
+            segments[..DAY_SEGMENTS]
+                .iter()
+                .filter_map(|segment| segment.rain)
+                .sum::<f32>()
+        });
+
+        Ok(meshtastic::protobufs::EnvironmentMetrics {
             temperature: Some(fcs.temp.temp),
-            relative_humidity: Some(fcs.humidity),
+            relative_humidity: Some(fcs.humidity * 100.0),
             barometric_pressure: Some(fcs.pressure.ground_level),
             gas_resistance: None,
             voltage: None,
@@ -209,11 +209,11 @@ impl From<&Forecast> for meshtastic::protobufs::EnvironmentMetrics {
             wind_gust: Some(fcs.wind.gust),
             wind_lull: None,
             radiation: None,
-            rainfall_1h: rainfall_1h,
-            rainfall_24h: rainfall_24h,
+            rainfall_1h: Some(rainfall_1h),
+            rainfall_24h,
             soil_moisture: None,
             soil_temperature: None,
-        }
+        })
     }
 }
 
@@ -229,6 +229,7 @@ impl Deref for Weather {
             Self::Snow(s) => s,
             Self::Thunderstorm(s) => s,
             Self::Atmosphere(a) => a,
+            Self::Unknown(_, s) => s,
         }
     }
 }
@@ -248,6 +249,7 @@ impl Deref for Atmosphere {
             Self::Smoke(s) => s,
             Self::Squall(s) => s,
             Self::Tornado(s) => s,
+            Self::Unknown(_, s) => s,
         }
     }
 }
@@ -262,9 +264,9 @@ impl Weather {
             700..800 => Self::Atmosphere(Atmosphere::from_id(id, s)),
             800 => Self::Clear(s),
             801..810 => Self::Clouds(s),
-            invalid_id => {
-                tracing::error!("Weather: Invalid Weather ID {}", invalid_id);
-                panic!("Weather: Invalid Weather ID {}", invalid_id);
+            unknown_id => {
+                tracing::warn!("Weather: Unknown Weather ID {}: {}", unknown_id, s);
+                Self::Unknown(unknown_id, s)
             }
         }
     }
@@ -283,23 +285,79 @@ impl Atmosphere {
             762 => Self::Ash(s),
             771 => Self::Squall(s),
             781 => Self::Tornado(s),
-            invalid_id => {
-                tracing::error!("Atmosphere: Invalid Atmosphere ID {}", invalid_id);
-                panic!("Atmosphere: Invalid Atmosphere ID {}", invalid_id);
+            unknown_id => {
+                tracing::warn!("Atmosphere: Unknown Atmosphere ID {}: {}", unknown_id, s);
+                Self::Unknown(unknown_id, s)
             }
         }
     }
 }
 
-impl From<char> for DayTime {
-    fn from(ch: char) -> Self {
+impl TryFrom<char> for DayTime {
+    type Error = Error;
+
+    fn try_from(ch: char) -> Result<Self, Self::Error> {
         match ch.to_ascii_lowercase() {
-            'd' => Self::Day,
-            'n' => Self::Night,
-            ch => panic!(
-                "Failed to create `DayTime` from {}. `d` and `n` are valid.",
-                ch
-            ),
+            'd' => Ok(Self::Day),
+            'n' => Ok(Self::Night),
+            ch => Err(Error::InvalidDayTime(ch)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = "description";
+
+    #[test]
+    fn every_weather_id_maps_to_a_variant() {
+        for id in u16::MIN..=u16::MAX {
+            let weather = Weather::from_id(id, DESCRIPTION.to_string());
+
+            assert_eq!(weather.as_str(), DESCRIPTION, "ID {}", id);
+            match weather {
+                Weather::Unknown(unknown_id, _) => {
+                    assert_eq!(unknown_id, id);
+                    assert!(!matches!(id, 200..400 | 500..810), "ID {}", id);
+                }
+                Weather::Atmosphere(_) => assert!((700..800).contains(&id), "ID {}", id),
+                _ => assert!(matches!(id, 200..400 | 500..700 | 800..810), "ID {}", id),
+            };
+        }
+    }
+
+    #[test]
+    fn every_atmosphere_id_maps_to_a_variant() {
+        const KNOWN: [u16; 10] = [701, 711, 721, 731, 741, 751, 761, 762, 771, 781];
+
+        for id in u16::MIN..=u16::MAX {
+            let atmosphere = Atmosphere::from_id(id, DESCRIPTION.to_string());
+
+            assert_eq!(atmosphere.as_str(), DESCRIPTION, "ID {}", id);
+            match atmosphere {
+                Atmosphere::Unknown(unknown_id, _) => {
+                    assert_eq!(unknown_id, id);
+                    assert!(!KNOWN.contains(&id), "ID {}", id);
+                }
+                _ => assert!(KNOWN.contains(&id), "ID {}", id),
+            };
+        }
+    }
+
+    #[test]
+    fn only_d_and_n_are_day_times() {
+        for ch in char::MIN..=char::MAX {
+            match DayTime::try_from(ch) {
+                Ok(DayTime::Day) => assert!(matches!(ch, 'd' | 'D')),
+                Ok(DayTime::Night) => assert!(matches!(ch, 'n' | 'N')),
+                Err(Error::InvalidDayTime(invalid)) => {
+                    assert!(!matches!(ch, 'd' | 'D' | 'n' | 'N'));
+                    assert_eq!(invalid, ch.to_ascii_lowercase());
+                }
+                Err(e) => panic!("Unexpected error for {:?}: {}", ch, e),
+            };
         }
     }
 }
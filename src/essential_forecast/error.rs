@@ -0,0 +1,7 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid Day Time: {0:?}. `d` and `n` are valid.")]
+    InvalidDayTime(char),
+    #[error("The forecast has no segments")]
+    NoSegments,
+}
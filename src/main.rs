@@ -7,6 +7,7 @@ mod bot;
 mod config;
 mod consts;
 mod essential_forecast;
+mod preferences;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, path::Path};
+
+use open_weather_map_api::localization::{Localization, Units};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::command::Coordinates;
+
+pub mod error;
+
+use error::Error;
+
+/// Settings the nodes chose for themselves.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Preferences {
+    nodes: HashMap<u32, NodePreferences>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NodePreferences {
+    pub units: Option<Units>,
+    /// OWM language code.
+    pub lang: Option<String>,
+    pub subscription: Option<Subscription>,
+}
+
+/// A forecast delivered every day at a fixed time.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// The delivery time in the local time of the bot.
+    pub hour: u32,
+    pub minute: u32,
+    /// The location of the forecast. `None` for the bot location.
+    pub coords: Option<Coordinates>,
+}
+
+impl Preferences {
+    /// Load the preferences from `path`. If not existent return empty preferences.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let preferences = if tokio::fs::try_exists(&path).await? {
+            let preferences_string = tokio::fs::read_to_string(path).await?;
+            serde_json::from_str(&preferences_string)?
+        } else {
+            Self::default()
+        };
+
+        tracing::debug!("Loaded preferences of {} nodes", preferences.nodes.len());
+
+        Ok(preferences)
+    }
+
+    /// Write the preferences to file.
+    pub async fn write(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let preferences_string = serde_json::to_string_pretty(self)?;
+        tokio::fs::write(&path, preferences_string).await?;
+
+        tracing::debug!("Wrote preferences to {}", path.as_ref().to_string_lossy());
+
+        Ok(())
+    }
+
+    /// Get the preferences of a node to change them.
+    pub fn node_mut(&mut self, node: u32) -> &mut NodePreferences {
+        self.nodes.entry(node).or_default()
+    }
+
+    /// All nodes with a subscription.
+    pub fn subscriptions(&self) -> impl Iterator<Item = (u32, &Subscription)> {
+        self.nodes.iter().filter_map(|(node, preferences)| {
+            preferences
+                .subscription
+                .as_ref()
+                .map(|subscription| (*node, subscription))
+        })
+    }
+
+    /// The localization for a node with `default` for everything the node did not choose.
+    pub fn localization(&self, node: u32, default: &Localization) -> Localization {
+        let Some(preferences) = self.nodes.get(&node) else {
+            return default.clone();
+        };
+
+        Localization::new(
+            preferences.units.unwrap_or(default.units),
+            preferences
+                .lang
+                .clone()
+                .unwrap_or_else(|| default.lang.clone()),
+        )
+    }
+}
+
+impl Subscription {
+    /// The latest delivery time at or before `now`.
+    pub fn previous_delivery(
+        &self,
+        now: chrono::DateTime<chrono::Local>,
+    ) -> Option<chrono::DateTime<chrono::Local>> {
+        let today = self.delivery_on(now.date_naive());
+        match today {
+            Some(delivery) if delivery <= now => Some(delivery),
+            _ => self.delivery_on(now.date_naive().pred_opt()?),
+        }
+    }
+
+    /// The first delivery time after `now`.
+    pub fn next_delivery(
+        &self,
+        now: chrono::DateTime<chrono::Local>,
+    ) -> Option<chrono::DateTime<chrono::Local>> {
+        let today = self.delivery_on(now.date_naive());
+        match today {
+            Some(delivery) if delivery > now => Some(delivery),
+            _ => self.delivery_on(now.date_naive().succ_opt()?),
+        }
+    }
+
+    /// The delivery time on `date`. `None` if the time does not exist on that day, e.g. in a DST gap.
+    fn delivery_on(&self, date: chrono::NaiveDate) -> Option<chrono::DateTime<chrono::Local>> {
+        date.and_hms_opt(self.hour, self.minute, 0)?
+            .and_local_timezone(chrono::Local)
+            .earliest()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(day: u32, hour: u32, minute: u32) -> chrono::DateTime<chrono::Local> {
+        chrono::Local
+            .with_ymd_and_hms(2024, 6, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn subscription(hour: u32, minute: u32) -> Subscription {
+        Subscription {
+            hour,
+            minute,
+            coords: None,
+        }
+    }
+
+    #[test]
+    fn delivers_later_today() {
+        let subscription = subscription(7, 30);
+
+        assert_eq!(
+            subscription.next_delivery(at(10, 6, 0)),
+            Some(at(10, 7, 30))
+        );
+        assert_eq!(
+            subscription.previous_delivery(at(10, 6, 0)),
+            Some(at(9, 7, 30))
+        );
+    }
+
+    #[test]
+    fn delivery_time_counts_as_previous() {
+        let subscription = subscription(7, 30);
+
+        assert_eq!(
+            subscription.previous_delivery(at(10, 7, 30)),
+            Some(at(10, 7, 30))
+        );
+        assert_eq!(
+            subscription.next_delivery(at(10, 7, 30)),
+            Some(at(11, 7, 30))
+        );
+    }
+
+    #[test]
+    fn wraps_past_midnight() {
+        let subscription = subscription(0, 15);
+
+        assert_eq!(
+            subscription.next_delivery(at(10, 23, 50)),
+            Some(at(11, 0, 15))
+        );
+        assert_eq!(
+            subscription.previous_delivery(at(11, 0, 10)),
+            Some(at(10, 0, 15))
+        );
+        assert_eq!(
+            subscription.previous_delivery(at(11, 0, 20)),
+            Some(at(11, 0, 15))
+        );
+    }
+
+    #[test]
+    fn late_delivery_is_previous_after_midnight() {
+        let subscription = subscription(23, 45);
+
+        assert_eq!(
+            subscription.previous_delivery(at(11, 0, 5)),
+            Some(at(10, 23, 45))
+        );
+        assert_eq!(
+            subscription.next_delivery(at(11, 0, 5)),
+            Some(at(11, 23, 45))
+        );
+    }
+}